@@ -0,0 +1,1232 @@
+use rand::{Rng, RngCore};
+
+use std::f32::consts::PI;
+
+use crate::colors::*;
+use crate::sampling::*;
+use crate::tuples::{dot, mul, reflect, sub, vector, Tuple};
+
+/// Direction picked by a BSDF together with the value of the BSDF for it and
+/// the probability density of having picked it.
+#[derive(Clone, Debug)]
+pub struct BsdfSample {
+    pub direction: Tuple,
+    pub value: Color,
+    pub pdf: f32,
+}
+
+/// How a surface scatters light. Directions point away from the surface and
+/// the normal is on the same side as `outgoing`.
+pub trait Bsdf {
+    /// Fraction of the light arriving from `incoming` that leaves towards `outgoing`.
+    fn evaluate(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color;
+
+    /// Picks an incoming direction with a density close to the shape of the BSDF.
+    fn sample(
+        self: &Self,
+        normal: &Tuple,
+        outgoing: &Tuple,
+        rng: &mut dyn RngCore,
+    ) -> Option<BsdfSample>;
+
+    /// Density of `sample` picking `incoming`.
+    fn pdf(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> f32;
+
+    /// Part of `evaluate` scattering light evenly, as opposed to the glossy
+    /// lobes, to tell diffuse and specular light apart. None by default.
+    fn evaluate_diffuse(
+        self: &Self,
+        _normal: &Tuple,
+        _outgoing: &Tuple,
+        _incoming: &Tuple,
+    ) -> Color {
+        Color::black()
+    }
+}
+
+/// Diffuse part of the Phong like models: a lambertian lobe.
+fn lambertian_part(diffuse: &Color, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+    if dot(normal, incoming) <= 0.0 || dot(normal, outgoing) <= 0.0 {
+        return Color::black();
+    }
+    multiply(diffuse, 1.0 / PI)
+}
+
+fn average(color: &Color) -> f32 {
+    (color.r + color.g + color.b) / 3.0
+}
+
+/// Mirror image of `outgoing` around the normal.
+fn mirror(outgoing: &Tuple, normal: &Tuple) -> Tuple {
+    reflect(&outgoing.negate(), normal)
+}
+
+/// Direction around `axis` following a cosine lobe raised to `exponent`.
+fn sample_lobe(axis: &Tuple, exponent: f32, u1: f32, u2: f32) -> Tuple {
+    let cos_theta = u1.powf(1.0 / (exponent + 1.0));
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    let local = vector(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+    to_world(&local, axis).normalize()
+}
+
+fn lobe_pdf(cos_theta: f32, exponent: f32) -> f32 {
+    if cos_theta <= 0.0 {
+        return 0.0;
+    }
+    (exponent + 1.0) / (2.0 * PI) * cos_theta.powf(exponent)
+}
+
+#[derive(Clone, Debug)]
+pub struct Lambertian {
+    pub albedo: Color,
+}
+
+impl Bsdf for Lambertian {
+    fn evaluate(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        if dot(normal, incoming) <= 0.0 || dot(normal, outgoing) <= 0.0 {
+            return Color::black();
+        }
+        multiply(&self.albedo, 1.0 / PI)
+    }
+
+    fn sample(
+        self: &Self,
+        normal: &Tuple,
+        outgoing: &Tuple,
+        rng: &mut dyn RngCore,
+    ) -> Option<BsdfSample> {
+        let direction = cosine_sample_hemisphere(normal, rng.gen(), rng.gen());
+        Some(BsdfSample {
+            value: self.evaluate(normal, outgoing, &direction),
+            pdf: self.pdf(normal, outgoing, &direction),
+            direction,
+        })
+    }
+
+    fn pdf(self: &Self, normal: &Tuple, _outgoing: &Tuple, incoming: &Tuple) -> f32 {
+        cosine_hemisphere_pdf(normal, incoming)
+    }
+
+    fn evaluate_diffuse(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        self.evaluate(normal, outgoing, incoming)
+    }
+}
+
+/// Energy normalized Phong: a lambertian lobe plus a specular lobe around the
+/// mirror direction of `outgoing`.
+#[derive(Clone, Debug)]
+pub struct Phong {
+    pub diffuse: Color,
+    pub specular: Color,
+    pub shininess: f32,
+}
+
+impl Phong {
+    fn specular_probability(self: &Self) -> f32 {
+        let total = average(&self.diffuse) + average(&self.specular);
+        if total > 0.0 {
+            average(&self.specular) / total
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Bsdf for Phong {
+    fn evaluate(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        if dot(normal, incoming) <= 0.0 || dot(normal, outgoing) <= 0.0 {
+            return Color::black();
+        }
+        let cos_alpha = dot(&mirror(outgoing, normal), incoming).max(0.0);
+        let normalization = (self.shininess + 2.0) / (2.0 * PI);
+        add(
+            multiply(&self.diffuse, 1.0 / PI),
+            multiply(
+                &self.specular,
+                normalization * cos_alpha.powf(self.shininess),
+            ),
+        )
+    }
+
+    fn sample(
+        self: &Self,
+        normal: &Tuple,
+        outgoing: &Tuple,
+        rng: &mut dyn RngCore,
+    ) -> Option<BsdfSample> {
+        let direction = if rng.gen::<f32>() < self.specular_probability() {
+            sample_lobe(
+                &mirror(outgoing, normal),
+                self.shininess,
+                rng.gen(),
+                rng.gen(),
+            )
+        } else {
+            cosine_sample_hemisphere(normal, rng.gen(), rng.gen())
+        };
+        if dot(normal, &direction) <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            value: self.evaluate(normal, outgoing, &direction),
+            pdf: self.pdf(normal, outgoing, &direction),
+            direction,
+        })
+    }
+
+    fn pdf(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> f32 {
+        let specular_probability = self.specular_probability();
+        let cos_alpha = dot(&mirror(outgoing, normal), incoming);
+        specular_probability * lobe_pdf(cos_alpha, self.shininess)
+            + (1.0 - specular_probability) * cosine_hemisphere_pdf(normal, incoming)
+    }
+
+    fn evaluate_diffuse(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        lambertian_part(&self.diffuse, normal, outgoing, incoming)
+    }
+}
+
+/// Energy normalized Blinn-Phong: like `Phong` but the specular lobe is
+/// measured between the normal and the half vector.
+#[derive(Clone, Debug)]
+pub struct BlinnPhong {
+    pub diffuse: Color,
+    pub specular: Color,
+    pub shininess: f32,
+}
+
+impl BlinnPhong {
+    fn specular_probability(self: &Self) -> f32 {
+        let total = average(&self.diffuse) + average(&self.specular);
+        if total > 0.0 {
+            average(&self.specular) / total
+        } else {
+            0.0
+        }
+    }
+}
+
+fn half_vector(outgoing: &Tuple, incoming: &Tuple) -> Tuple {
+    crate::tuples::add(outgoing, incoming).normalize()
+}
+
+impl Bsdf for BlinnPhong {
+    fn evaluate(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        if dot(normal, incoming) <= 0.0 || dot(normal, outgoing) <= 0.0 {
+            return Color::black();
+        }
+        let cos_half = dot(normal, &half_vector(outgoing, incoming)).max(0.0);
+        let normalization = (self.shininess + 8.0) / (8.0 * PI);
+        add(
+            multiply(&self.diffuse, 1.0 / PI),
+            multiply(
+                &self.specular,
+                normalization * cos_half.powf(self.shininess),
+            ),
+        )
+    }
+
+    fn sample(
+        self: &Self,
+        normal: &Tuple,
+        outgoing: &Tuple,
+        rng: &mut dyn RngCore,
+    ) -> Option<BsdfSample> {
+        let direction = if rng.gen::<f32>() < self.specular_probability() {
+            let half = sample_lobe(normal, self.shininess, rng.gen(), rng.gen());
+            reflect(&outgoing.negate(), &half)
+        } else {
+            cosine_sample_hemisphere(normal, rng.gen(), rng.gen())
+        };
+        if dot(normal, &direction) <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            value: self.evaluate(normal, outgoing, &direction),
+            pdf: self.pdf(normal, outgoing, &direction),
+            direction,
+        })
+    }
+
+    fn pdf(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> f32 {
+        let specular_probability = self.specular_probability();
+        let half = half_vector(outgoing, incoming);
+        let outgoing_dot_half = dot(outgoing, &half);
+        let specular_pdf = if outgoing_dot_half > 0.0 {
+            lobe_pdf(dot(normal, &half), self.shininess) / (4.0 * outgoing_dot_half)
+        } else {
+            0.0
+        };
+        specular_probability * specular_pdf
+            + (1.0 - specular_probability) * cosine_hemisphere_pdf(normal, incoming)
+    }
+
+    fn evaluate_diffuse(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        lambertian_part(&self.diffuse, normal, outgoing, incoming)
+    }
+}
+
+/// Diffuse reflection of rough surfaces like clay or plaster, which look
+/// flatter than lambertian ones. `roughness` is the standard deviation of the
+/// facet slopes, in radians.
+#[derive(Clone, Debug)]
+pub struct OrenNayar {
+    pub albedo: Color,
+    pub roughness: f32,
+}
+
+impl Bsdf for OrenNayar {
+    fn evaluate(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        let cos_in = dot(normal, incoming);
+        let cos_out = dot(normal, outgoing);
+        if cos_in <= 0.0 || cos_out <= 0.0 {
+            return Color::black();
+        }
+        let sigma2 = self.roughness * self.roughness;
+        let a = 1.0 - 0.5 * sigma2 / (sigma2 + 0.33);
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+        let sin_in = (1.0 - cos_in * cos_in).max(0.0).sqrt();
+        let sin_out = (1.0 - cos_out * cos_out).max(0.0).sqrt();
+        let cos_phi_difference = if sin_in > 0.0001 && sin_out > 0.0001 {
+            let tangent_in = sub(incoming, &mul(normal, cos_in)).normalize();
+            let tangent_out = sub(outgoing, &mul(normal, cos_out)).normalize();
+            dot(&tangent_in, &tangent_out).max(0.0)
+        } else {
+            0.0
+        };
+        // alpha is the largest of both angles and beta the smallest.
+        let (sin_alpha, tan_beta) = if cos_in > cos_out {
+            (sin_out, sin_in / cos_in)
+        } else {
+            (sin_in, sin_out / cos_out)
+        };
+
+        multiply(
+            &self.albedo,
+            (a + b * cos_phi_difference * sin_alpha * tan_beta) / PI,
+        )
+    }
+
+    fn sample(
+        self: &Self,
+        normal: &Tuple,
+        outgoing: &Tuple,
+        rng: &mut dyn RngCore,
+    ) -> Option<BsdfSample> {
+        let direction = cosine_sample_hemisphere(normal, rng.gen(), rng.gen());
+        Some(BsdfSample {
+            value: self.evaluate(normal, outgoing, &direction),
+            pdf: self.pdf(normal, outgoing, &direction),
+            direction,
+        })
+    }
+
+    fn pdf(self: &Self, normal: &Tuple, _outgoing: &Tuple, incoming: &Tuple) -> f32 {
+        cosine_hemisphere_pdf(normal, incoming)
+    }
+
+    fn evaluate_diffuse(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        self.evaluate(normal, outgoing, incoming)
+    }
+}
+
+/// Cook-Torrance microfacet reflection with the GGX distribution, Smith
+/// shadowing and Schlick's Fresnel approximation, parameterized like the
+/// glTF metallic/roughness model. Dielectrics (`metallic` 0) add a lambertian
+/// lobe under the specular one; metals (`metallic` 1) tint the specular
+/// reflection with the base color.
+#[derive(Clone, Debug)]
+pub struct Microfacet {
+    pub base_color: Color,
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+impl Microfacet {
+    fn alpha(self: &Self) -> f32 {
+        (self.roughness * self.roughness).max(0.001)
+    }
+
+    /// Reflectance at normal incidence.
+    fn f0(self: &Self) -> Color {
+        let dielectric = Color::new(0.04, 0.04, 0.04);
+        add(
+            multiply(&dielectric, 1.0 - self.metallic),
+            multiply(&self.base_color, self.metallic),
+        )
+    }
+
+    fn diffuse_color(self: &Self) -> Color {
+        multiply(&self.base_color, 1.0 - self.metallic)
+    }
+
+    fn specular_probability(self: &Self, normal: &Tuple, outgoing: &Tuple) -> f32 {
+        let specular = average(&schlick(&self.f0(), dot(normal, outgoing).max(0.0)));
+        let diffuse = average(&self.diffuse_color());
+        if specular + diffuse > 0.0 {
+            specular / (specular + diffuse)
+        } else {
+            0.0
+        }
+    }
+}
+
+pub fn schlick(f0: &Color, cosine: f32) -> Color {
+    let factor = (1.0 - cosine).max(0.0).powi(5);
+    add(
+        multiply(f0, 1.0 - factor),
+        multiply(&Color::new(1.0, 1.0, 1.0), factor),
+    )
+}
+
+/// GGX (Trowbridge-Reitz) distribution of microfacet normals.
+pub fn ggx_distribution(cos_half: f32, alpha: f32) -> f32 {
+    if cos_half <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    let denominator = cos_half * cos_half * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * denominator * denominator)
+}
+
+/// Smith masking for one direction with the GGX distribution.
+pub fn smith_g1(cosine: f32, alpha: f32) -> f32 {
+    if cosine <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    2.0 * cosine / (cosine + (alpha2 + (1.0 - alpha2) * cosine * cosine).sqrt())
+}
+
+/// Microfacet normal around `normal` with density `D(h) * cos(theta_h)`.
+pub fn sample_ggx_half_vector(normal: &Tuple, alpha: f32, u1: f32, u2: f32) -> Tuple {
+    let alpha2 = alpha * alpha;
+    let cos_theta = ((1.0 - u1) / (1.0 + (alpha2 - 1.0) * u1)).max(0.0).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    let local = vector(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+    to_world(&local, normal).normalize()
+}
+
+impl Bsdf for Microfacet {
+    fn evaluate(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        let cos_in = dot(normal, incoming);
+        let cos_out = dot(normal, outgoing);
+        if cos_in <= 0.0 || cos_out <= 0.0 {
+            return Color::black();
+        }
+        let alpha = self.alpha();
+        let half = half_vector(outgoing, incoming);
+        let fresnel = schlick(&self.f0(), dot(incoming, &half).max(0.0));
+        let distribution = ggx_distribution(dot(normal, &half), alpha);
+        let shadowing = smith_g1(cos_in, alpha) * smith_g1(cos_out, alpha);
+        let specular = multiply(
+            &fresnel,
+            distribution * shadowing / (4.0 * cos_in * cos_out),
+        );
+        add(self.evaluate_diffuse(normal, outgoing, incoming), specular)
+    }
+
+    fn sample(
+        self: &Self,
+        normal: &Tuple,
+        outgoing: &Tuple,
+        rng: &mut dyn RngCore,
+    ) -> Option<BsdfSample> {
+        let direction = if rng.gen::<f32>() < self.specular_probability(normal, outgoing) {
+            let half = sample_ggx_half_vector(normal, self.alpha(), rng.gen(), rng.gen());
+            reflect(&outgoing.negate(), &half)
+        } else {
+            cosine_sample_hemisphere(normal, rng.gen(), rng.gen())
+        };
+        if dot(normal, &direction) <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            value: self.evaluate(normal, outgoing, &direction),
+            pdf: self.pdf(normal, outgoing, &direction),
+            direction,
+        })
+    }
+
+    fn pdf(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> f32 {
+        let specular_probability = self.specular_probability(normal, outgoing);
+        let half = half_vector(outgoing, incoming);
+        let outgoing_dot_half = dot(outgoing, &half);
+        let specular_pdf = if outgoing_dot_half > 0.0 {
+            let cos_half = dot(normal, &half);
+            ggx_distribution(cos_half, self.alpha()) * cos_half / (4.0 * outgoing_dot_half)
+        } else {
+            0.0
+        };
+        specular_probability * specular_pdf
+            + (1.0 - specular_probability) * cosine_hemisphere_pdf(normal, incoming)
+    }
+
+    /// The light the specular reflection leaves to the lambertian lobe.
+    fn evaluate_diffuse(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        if dot(normal, incoming) <= 0.0 || dot(normal, outgoing) <= 0.0 {
+            return Color::black();
+        }
+        let half = half_vector(outgoing, incoming);
+        let fresnel = schlick(&self.f0(), dot(incoming, &half).max(0.0));
+        let not_reflected = crate::colors::sub(Color::new(1.0, 1.0, 1.0), fresnel);
+        multiply(
+            &hadamard_product(&not_reflected, &self.diffuse_color()),
+            1.0 / PI,
+        )
+    }
+}
+
+/// Fraction of unpolarized light reflected by a smooth interface. `eta` is
+/// the index of refraction on the far side divided by the one on the side
+/// the light arrives from.
+pub fn fresnel_dielectric(cos_incident: f32, eta: f32) -> f32 {
+    let cos_incident = cos_incident.min(1.0);
+    let sin2_transmitted = (1.0 - cos_incident * cos_incident).max(0.0) / (eta * eta);
+    if sin2_transmitted >= 1.0 {
+        return 1.0;
+    }
+    let cos_transmitted = (1.0 - sin2_transmitted).sqrt();
+    let s = (cos_incident - eta * cos_transmitted) / (cos_incident + eta * cos_transmitted);
+    let p = (eta * cos_incident - cos_transmitted) / (eta * cos_incident + cos_transmitted);
+    (s * s + p * p) / 2.0
+}
+
+/// Direction taken by `outgoing` when it crosses the microfacet `half` into a
+/// medium `eta` times denser, or `None` on total internal reflection.
+fn refract(outgoing: &Tuple, half: &Tuple, eta: f32) -> Option<Tuple> {
+    let cos_outgoing = dot(outgoing, half);
+    let sin2_transmitted = (1.0 - cos_outgoing * cos_outgoing).max(0.0) / (eta * eta);
+    if sin2_transmitted >= 1.0 {
+        return None;
+    }
+    let cos_transmitted = (1.0 - sin2_transmitted).sqrt();
+    let direction = sub(
+        &mul(half, cos_outgoing / eta - cos_transmitted),
+        &mul(outgoing, 1.0 / eta),
+    );
+    Some(direction.normalize())
+}
+
+/// Microfacet glass with GGX roughness (Walter et al. 2007). Rough values give
+/// frosted glass and values close to 0 give clear glass. Unlike the other
+/// BSDFs, `incoming` may be below the surface: those directions are the
+/// transmitted ones. `eta` is the index of refraction behind the surface
+/// divided by the one on the side of the normal, and `tint` filters the
+/// transmitted light.
+#[derive(Clone, Debug)]
+pub struct RoughDielectric {
+    pub tint: Color,
+    pub eta: f32,
+    pub roughness: f32,
+}
+
+impl RoughDielectric {
+    fn alpha(self: &Self) -> f32 {
+        (self.roughness * self.roughness).max(0.001)
+    }
+
+    /// Microfacet normal that turns `outgoing` into `incoming`, on the side of
+    /// the normal.
+    fn transmission_half_vector(
+        self: &Self,
+        normal: &Tuple,
+        outgoing: &Tuple,
+        incoming: &Tuple,
+    ) -> Tuple {
+        let half = crate::tuples::add(outgoing, &mul(incoming, self.eta)).normalize();
+        if dot(normal, &half) < 0.0 {
+            half.negate()
+        } else {
+            half
+        }
+    }
+}
+
+impl Bsdf for RoughDielectric {
+    fn evaluate(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        let cos_in = dot(normal, incoming);
+        let cos_out = dot(normal, outgoing);
+        if cos_out <= 0.0 || cos_in == 0.0 {
+            return Color::black();
+        }
+        let alpha = self.alpha();
+        let shadowing = smith_g1(cos_in.abs(), alpha) * smith_g1(cos_out, alpha);
+
+        if cos_in > 0.0 {
+            let half = half_vector(outgoing, incoming);
+            let fresnel = fresnel_dielectric(dot(outgoing, &half), self.eta);
+            let distribution = ggx_distribution(dot(normal, &half), alpha);
+            let value = fresnel * distribution * shadowing / (4.0 * cos_in * cos_out);
+            return Color::new(value, value, value);
+        }
+
+        let half = self.transmission_half_vector(normal, outgoing, incoming);
+        let outgoing_dot_half = dot(outgoing, &half);
+        let incoming_dot_half = dot(incoming, &half);
+        if outgoing_dot_half <= 0.0 || incoming_dot_half >= 0.0 {
+            return Color::black();
+        }
+        let fresnel = fresnel_dielectric(outgoing_dot_half, self.eta);
+        let distribution = ggx_distribution(dot(normal, &half), alpha);
+        let denominator = outgoing_dot_half + self.eta * incoming_dot_half;
+        // Radiance is compressed by eta^2 when it enters a denser medium,
+        // which cancels the eta^2 of the change of variables.
+        let value =
+            (1.0 - fresnel) * distribution * shadowing * outgoing_dot_half * -incoming_dot_half
+                / (cos_out * -cos_in * denominator * denominator);
+        multiply(&self.tint, value)
+    }
+
+    fn sample(
+        self: &Self,
+        normal: &Tuple,
+        outgoing: &Tuple,
+        rng: &mut dyn RngCore,
+    ) -> Option<BsdfSample> {
+        let half = sample_ggx_half_vector(normal, self.alpha(), rng.gen(), rng.gen());
+        let outgoing_dot_half = dot(outgoing, &half);
+        if outgoing_dot_half <= 0.0 {
+            return None;
+        }
+        let fresnel = fresnel_dielectric(outgoing_dot_half, self.eta);
+        let direction = if rng.gen::<f32>() < fresnel {
+            reflect(&outgoing.negate(), &half)
+        } else {
+            refract(outgoing, &half, self.eta)?
+        };
+        let cos_in = dot(normal, &direction);
+        if cos_in == 0.0 || (cos_in > 0.0) != (dot(&half, &direction) > 0.0) {
+            return None;
+        }
+        Some(BsdfSample {
+            value: self.evaluate(normal, outgoing, &direction),
+            pdf: self.pdf(normal, outgoing, &direction),
+            direction,
+        })
+    }
+
+    fn pdf(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> f32 {
+        let cos_in = dot(normal, incoming);
+        if dot(normal, outgoing) <= 0.0 || cos_in == 0.0 {
+            return 0.0;
+        }
+        let alpha = self.alpha();
+
+        if cos_in > 0.0 {
+            let half = half_vector(outgoing, incoming);
+            let outgoing_dot_half = dot(outgoing, &half);
+            if outgoing_dot_half <= 0.0 {
+                return 0.0;
+            }
+            let cos_half = dot(normal, &half);
+            let fresnel = fresnel_dielectric(outgoing_dot_half, self.eta);
+            return fresnel * ggx_distribution(cos_half, alpha) * cos_half
+                / (4.0 * outgoing_dot_half);
+        }
+
+        let half = self.transmission_half_vector(normal, outgoing, incoming);
+        let outgoing_dot_half = dot(outgoing, &half);
+        let incoming_dot_half = dot(incoming, &half);
+        if outgoing_dot_half <= 0.0 || incoming_dot_half >= 0.0 {
+            return 0.0;
+        }
+        let cos_half = dot(normal, &half);
+        let fresnel = fresnel_dielectric(outgoing_dot_half, self.eta);
+        let denominator = outgoing_dot_half + self.eta * incoming_dot_half;
+        let jacobian = self.eta * self.eta * -incoming_dot_half / (denominator * denominator);
+        (1.0 - fresnel) * ggx_distribution(cos_half, alpha) * cos_half * jacobian
+    }
+}
+
+/// Thin transparent film on top of a surface, like oil on water or the
+/// anodized layer of titanium. Light bouncing on both sides of the film
+/// interferes and tints the reflection depending on the viewing angle.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ThinFilm {
+    /// Thickness in nanometers.
+    pub thickness: f32,
+    pub ior: f32,
+}
+
+/// Wavelengths in nanometers standing for the red, green and blue channels.
+const WAVELENGTHS: [f32; 3] = [650.0, 510.0, 475.0];
+
+/// Reflectance of a surface with index of refraction `base_ior` covered by
+/// `film`, seen from the air. Uses the Airy formula for a single film with
+/// both polarizations averaged.
+pub fn thin_film_reflectance(film: &ThinFilm, base_ior: f32, cos_incident: f32) -> Color {
+    let cos_incident = cos_incident.clamp(0.0, 1.0);
+    let sin2_incident = 1.0 - cos_incident * cos_incident;
+    let cos_film = (1.0 - sin2_incident / (film.ior * film.ior))
+        .max(0.0)
+        .sqrt();
+    let cos_base = (1.0 - sin2_incident / (base_ior * base_ior))
+        .max(0.0)
+        .sqrt();
+
+    let airy = |r12: f32, r23: f32, cos_phase: f32| {
+        let cross_term = 2.0 * r12 * r23 * cos_phase;
+        (r12 * r12 + r23 * r23 + cross_term) / (1.0 + r12 * r12 * r23 * r23 + cross_term)
+    };
+    let s12 = (cos_incident - film.ior * cos_film) / (cos_incident + film.ior * cos_film);
+    let s23 =
+        (film.ior * cos_film - base_ior * cos_base) / (film.ior * cos_film + base_ior * cos_base);
+    let p12 = (film.ior * cos_incident - cos_film) / (film.ior * cos_incident + cos_film);
+    let p23 =
+        (base_ior * cos_film - film.ior * cos_base) / (base_ior * cos_film + film.ior * cos_base);
+
+    let channel = |wavelength: f32| {
+        let phase = 4.0 * PI * film.ior * film.thickness * cos_film / wavelength;
+        (airy(s12, s23, phase.cos()) + airy(p12, p23, phase.cos())) / 2.0
+    };
+    Color::new(
+        channel(WAVELENGTHS[0]),
+        channel(WAVELENGTHS[1]),
+        channel(WAVELENGTHS[2]),
+    )
+}
+
+/// Glossy varnish layer over another BSDF, like the clear coat of car paint.
+/// The coat reflects according to the Fresnel equations for `ior`, or those of
+/// `thin_film` when there is one, and the base receives whatever the coat
+/// lets through on the way in and out. `strength` scales the coat from 0
+/// (bare base) to 1.
+pub struct ClearCoat {
+    pub base: Box<dyn Bsdf>,
+    pub strength: f32,
+    pub ior: f32,
+    pub roughness: f32,
+    pub thin_film: Option<ThinFilm>,
+}
+
+impl ClearCoat {
+    fn alpha(self: &Self) -> f32 {
+        (self.roughness * self.roughness).max(0.001)
+    }
+
+    fn coat_reflectance(self: &Self, cosine: f32) -> Color {
+        let reflectance = match &self.thin_film {
+            Some(film) => thin_film_reflectance(film, self.ior, cosine),
+            None => {
+                let fresnel = fresnel_dielectric(cosine, self.ior);
+                Color::new(fresnel, fresnel, fresnel)
+            }
+        };
+        multiply(&reflectance, self.strength)
+    }
+
+    /// Fraction of the light reaching the base and coming back out.
+    fn through_coat(self: &Self, cos_in: f32, cos_out: f32) -> Color {
+        let white = Color::new(1.0, 1.0, 1.0);
+        hadamard_product(
+            &crate::colors::sub(white.clone(), self.coat_reflectance(cos_in)),
+            &crate::colors::sub(white, self.coat_reflectance(cos_out)),
+        )
+    }
+
+    fn coat_probability(self: &Self, normal: &Tuple, outgoing: &Tuple) -> f32 {
+        if self.strength <= 0.0 {
+            return 0.0;
+        }
+        let reflectance = average(&self.coat_reflectance(dot(normal, outgoing).max(0.0)));
+        reflectance.clamp(0.1, 0.9)
+    }
+}
+
+impl Bsdf for ClearCoat {
+    fn evaluate(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        let cos_in = dot(normal, incoming);
+        let cos_out = dot(normal, outgoing);
+        if cos_in <= 0.0 || cos_out <= 0.0 {
+            return Color::black();
+        }
+        let alpha = self.alpha();
+        let half = half_vector(outgoing, incoming);
+        let distribution = ggx_distribution(dot(normal, &half), alpha);
+        let shadowing = smith_g1(cos_in, alpha) * smith_g1(cos_out, alpha);
+        let coat = multiply(
+            &self.coat_reflectance(dot(outgoing, &half).max(0.0)),
+            distribution * shadowing / (4.0 * cos_in * cos_out),
+        );
+
+        let base = hadamard_product(
+            &self.through_coat(cos_in, cos_out),
+            &self.base.evaluate(normal, outgoing, incoming),
+        );
+        add(coat, base)
+    }
+
+    fn sample(
+        self: &Self,
+        normal: &Tuple,
+        outgoing: &Tuple,
+        rng: &mut dyn RngCore,
+    ) -> Option<BsdfSample> {
+        let direction = if rng.gen::<f32>() < self.coat_probability(normal, outgoing) {
+            let half = sample_ggx_half_vector(normal, self.alpha(), rng.gen(), rng.gen());
+            reflect(&outgoing.negate(), &half)
+        } else {
+            self.base.sample(normal, outgoing, rng)?.direction
+        };
+        if dot(normal, &direction) <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            value: self.evaluate(normal, outgoing, &direction),
+            pdf: self.pdf(normal, outgoing, &direction),
+            direction,
+        })
+    }
+
+    fn pdf(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> f32 {
+        let coat_probability = self.coat_probability(normal, outgoing);
+        let half = half_vector(outgoing, incoming);
+        let outgoing_dot_half = dot(outgoing, &half);
+        let coat_pdf = if outgoing_dot_half > 0.0 && dot(normal, incoming) > 0.0 {
+            let cos_half = dot(normal, &half);
+            ggx_distribution(cos_half, self.alpha()) * cos_half / (4.0 * outgoing_dot_half)
+        } else {
+            0.0
+        };
+        coat_probability * coat_pdf
+            + (1.0 - coat_probability) * self.base.pdf(normal, outgoing, incoming)
+    }
+
+    /// The diffuse part of the base, seen through the coat.
+    fn evaluate_diffuse(self: &Self, normal: &Tuple, outgoing: &Tuple, incoming: &Tuple) -> Color {
+        let cos_in = dot(normal, incoming);
+        let cos_out = dot(normal, outgoing);
+        if cos_in <= 0.0 || cos_out <= 0.0 {
+            return Color::black();
+        }
+        hadamard_product(
+            &self.through_coat(cos_in, cos_out),
+            &self.base.evaluate_diffuse(normal, outgoing, incoming),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bsdfs::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Monte Carlo estimate of the fraction of light scattered towards `outgoing`.
+    fn reflectance(bsdf: &dyn Bsdf, normal: &Tuple, outgoing: &Tuple) -> Color {
+        let mut rng = StdRng::seed_from_u64(1);
+        let samples = 20000;
+        let mut total = Color::black();
+        for _ in 0..samples {
+            if let Some(sample) = bsdf.sample(normal, outgoing, &mut rng) {
+                if sample.pdf > 0.0 {
+                    let cosine = dot(normal, &sample.direction).abs();
+                    total = add(total, multiply(&sample.value, cosine / sample.pdf));
+                }
+            }
+        }
+        multiply(&total, 1.0 / samples as f32)
+    }
+
+    fn assert_sample_is_consistent(bsdf: &dyn Bsdf) {
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(0.3, 1.0, 0.2).normalize();
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..50 {
+            if let Some(sample) = bsdf.sample(&normal, &outgoing, &mut rng) {
+                assert_abs_diff_eq!(
+                    sample.pdf,
+                    bsdf.pdf(&normal, &outgoing, &sample.direction),
+                    epsilon = 0.001 * sample.pdf.max(1.0)
+                );
+                assert_abs_diff_eq!(
+                    sample.value,
+                    bsdf.evaluate(&normal, &outgoing, &sample.direction),
+                    epsilon = 0.0001
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_lambertian_surface_reflects_its_albedo() {
+        let bsdf = Lambertian {
+            albedo: Color::new(0.5, 0.25, 1.0),
+        };
+        let normal = vector(0.0, 0.0, 1.0);
+        let reflected = reflectance(&bsdf, &normal, &vector(0.0, 0.6, 0.8));
+        assert_abs_diff_eq!(reflected, Color::new(0.5, 0.25, 1.0), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn a_lambertian_surface_does_not_reflect_from_below() {
+        let bsdf = Lambertian {
+            albedo: Color::new(1.0, 1.0, 1.0),
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        assert_abs_diff_eq!(
+            bsdf.evaluate(&normal, &normal, &vector(0.0, -1.0, 0.0)),
+            Color::black()
+        );
+    }
+
+    #[test]
+    fn phong_peaks_in_the_mirror_direction() {
+        let bsdf = Phong {
+            diffuse: Color::black(),
+            specular: Color::new(1.0, 1.0, 1.0),
+            shininess: 50.0,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(1.0, 1.0, 0.0).normalize();
+        let mirror = vector(-1.0, 1.0, 0.0).normalize();
+        let off_mirror = vector(-1.0, 2.0, 0.0).normalize();
+        assert!(
+            bsdf.evaluate(&normal, &outgoing, &mirror).r
+                > bsdf.evaluate(&normal, &outgoing, &off_mirror).r
+        );
+    }
+
+    #[test]
+    fn phong_does_not_create_energy() {
+        let bsdf = Phong {
+            diffuse: Color::new(0.5, 0.5, 0.5),
+            specular: Color::new(0.5, 0.5, 0.5),
+            shininess: 20.0,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let reflected = reflectance(&bsdf, &normal, &vector(0.0, 1.0, 0.0));
+        assert!(reflected.r <= 1.01);
+        assert!(reflected.r > 0.9);
+    }
+
+    #[test]
+    fn samples_agree_with_evaluate_and_pdf() {
+        let diffuse = Color::new(0.4, 0.4, 0.4);
+        let specular = Color::new(0.6, 0.6, 0.6);
+        assert_sample_is_consistent(&Lambertian {
+            albedo: diffuse.clone(),
+        });
+        assert_sample_is_consistent(&Phong {
+            diffuse: diffuse.clone(),
+            specular: specular.clone(),
+            shininess: 30.0,
+        });
+        assert_sample_is_consistent(&BlinnPhong {
+            diffuse: diffuse.clone(),
+            specular,
+            shininess: 30.0,
+        });
+        assert_sample_is_consistent(&OrenNayar {
+            albedo: diffuse,
+            roughness: 0.5,
+        });
+    }
+
+    #[test]
+    fn blinn_phong_does_not_create_energy() {
+        let bsdf = BlinnPhong {
+            diffuse: Color::black(),
+            specular: Color::new(1.0, 1.0, 1.0),
+            shininess: 40.0,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let reflected = reflectance(&bsdf, &normal, &vector(0.0, 1.0, 0.0));
+        assert!(reflected.r <= 1.05);
+    }
+
+    #[test]
+    fn smooth_oren_nayar_is_lambertian() {
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(0.5, 1.0, 0.0).normalize();
+        let incoming = vector(-0.2, 1.0, 0.7).normalize();
+        let oren_nayar = OrenNayar {
+            albedo: Color::new(0.8, 0.8, 0.8),
+            roughness: 0.0,
+        };
+        let lambertian = Lambertian {
+            albedo: Color::new(0.8, 0.8, 0.8),
+        };
+        assert_abs_diff_eq!(
+            oren_nayar.evaluate(&normal, &outgoing, &incoming),
+            lambertian.evaluate(&normal, &outgoing, &incoming)
+        );
+    }
+
+    #[test]
+    fn rough_oren_nayar_is_brighter_towards_the_light() {
+        let normal = vector(0.0, 1.0, 0.0);
+        let incoming = vector(1.0, 1.0, 0.0).normalize();
+        let bsdf = OrenNayar {
+            albedo: Color::new(0.8, 0.8, 0.8),
+            roughness: 0.6,
+        };
+        let back_to_light = bsdf.evaluate(&normal, &incoming, &incoming);
+        let away_from_light =
+            bsdf.evaluate(&normal, &vector(-1.0, 1.0, 0.0).normalize(), &incoming);
+        assert!(back_to_light.r > away_from_light.r);
+    }
+
+    #[test]
+    fn fresnel_at_normal_incidence_is_f0() {
+        let f0 = Color::new(0.04, 0.04, 0.04);
+        assert_abs_diff_eq!(schlick(&f0, 1.0), f0);
+        assert_abs_diff_eq!(schlick(&f0, 0.0), Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn the_ggx_distribution_is_normalized() {
+        // The projected area of the microfacets must add up to the macro surface.
+        let alpha = 0.3;
+        let steps = 20000;
+        let mut total = 0.0;
+        for i in 0..steps {
+            let cos_theta = (i as f32 + 0.5) / steps as f32;
+            total += ggx_distribution(cos_theta, alpha) * cos_theta * 2.0 * PI / steps as f32;
+        }
+        assert_abs_diff_eq!(total, 1.0, epsilon = 0.01);
+    }
+
+    #[test]
+    fn smooth_metals_reflect_their_base_color_in_the_mirror_direction() {
+        let bsdf = Microfacet {
+            base_color: Color::new(1.0, 0.5, 0.2),
+            metallic: 1.0,
+            roughness: 0.1,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(0.0, 1.0, 0.0);
+        let mirror = bsdf.evaluate(&normal, &outgoing, &vector(0.0, 1.0, 0.0));
+        let off_mirror = bsdf.evaluate(&normal, &outgoing, &vector(1.0, 1.0, 0.0).normalize());
+        assert!(mirror.r > 10.0 * off_mirror.r);
+        assert!(mirror.r > mirror.g && mirror.g > mirror.b);
+    }
+
+    #[test]
+    fn rough_metals_do_not_create_energy() {
+        let bsdf = Microfacet {
+            base_color: Color::new(1.0, 1.0, 1.0),
+            metallic: 1.0,
+            roughness: 0.5,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let reflected = reflectance(&bsdf, &normal, &vector(0.2, 1.0, 0.0).normalize());
+        assert!(reflected.r <= 1.01);
+        assert!(reflected.r > 0.8);
+    }
+
+    #[test]
+    fn dielectrics_keep_a_diffuse_base() {
+        let bsdf = Microfacet {
+            base_color: Color::new(0.0, 1.0, 0.0),
+            metallic: 0.0,
+            roughness: 0.5,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(1.0, 1.0, 0.0).normalize();
+        let incoming = vector(-0.3, 1.0, 0.6).normalize();
+        let value = bsdf.evaluate(&normal, &outgoing, &incoming);
+        assert!(value.g > value.r);
+        assert!(value.r > 0.0);
+    }
+
+    #[test]
+    fn microfacet_samples_agree_with_evaluate_and_pdf() {
+        assert_sample_is_consistent(&Microfacet {
+            base_color: Color::new(0.7, 0.6, 0.5),
+            metallic: 0.5,
+            roughness: 0.4,
+        });
+    }
+
+    #[test]
+    fn fresnel_of_glass_seen_from_the_air() {
+        assert_abs_diff_eq!(fresnel_dielectric(1.0, 1.5), 0.04, epsilon = 0.0001);
+        assert_abs_diff_eq!(fresnel_dielectric(0.0, 1.5), 1.0, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn light_leaving_glass_at_a_grazing_angle_is_totally_reflected() {
+        assert_abs_diff_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+    }
+
+    #[test]
+    fn rough_glass_transmits_most_light_at_normal_incidence() {
+        let bsdf = RoughDielectric {
+            tint: Color::new(1.0, 1.0, 1.0),
+            eta: 1.5,
+            roughness: 0.2,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        // Transmitted radiance is spread by eta^2 inside the denser medium.
+        let expected = 0.04 + 0.96 / (1.5 * 1.5);
+        let scattered = reflectance(&bsdf, &normal, &normal);
+        assert_abs_diff_eq!(scattered.r, expected, epsilon = 0.02);
+    }
+
+    #[test]
+    fn rough_glass_refracts_towards_the_normal() {
+        let bsdf = RoughDielectric {
+            tint: Color::new(1.0, 1.0, 1.0),
+            eta: 1.5,
+            roughness: 0.05,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(1.0, 1.0, 0.0).normalize();
+        let refracted = vector(-0.4714, -0.8819, 0.0);
+        let straight_through = outgoing.negate();
+        assert!(
+            bsdf.evaluate(&normal, &outgoing, &refracted).r
+                > bsdf.evaluate(&normal, &outgoing, &straight_through).r
+        );
+    }
+
+    #[test]
+    fn light_cannot_leave_glass_at_a_grazing_angle() {
+        let bsdf = RoughDielectric {
+            tint: Color::new(1.0, 1.0, 1.0),
+            eta: 1.0 / 1.5,
+            roughness: 0.01,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(1.0, 0.2, 0.0).normalize();
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..100 {
+            if let Some(sample) = bsdf.sample(&normal, &outgoing, &mut rng) {
+                assert!(dot(&normal, &sample.direction) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn rough_glass_samples_agree_with_evaluate_and_pdf() {
+        let tint = Color::new(0.9, 0.8, 0.7);
+        for eta in [1.5, 1.0 / 1.5].iter() {
+            assert_sample_is_consistent(&RoughDielectric {
+                tint: tint.clone(),
+                eta: *eta,
+                roughness: 0.4,
+            });
+        }
+    }
+
+    #[test]
+    fn a_film_without_thickness_is_the_bare_surface() {
+        let film = ThinFilm {
+            thickness: 0.0,
+            ior: 1.33,
+        };
+        let reflectance = thin_film_reflectance(&film, 1.5, 0.7);
+        let bare = fresnel_dielectric(0.7, 1.5);
+        assert_abs_diff_eq!(reflectance, Color::new(bare, bare, bare), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn thin_films_tint_the_reflection() {
+        let film = ThinFilm {
+            thickness: 300.0,
+            ior: 2.0,
+        };
+        let reflectance = thin_film_reflectance(&film, 1.5, 1.0);
+        assert!((reflectance.r - reflectance.g).abs() > 0.01);
+        assert!((reflectance.g - reflectance.b).abs() > 0.01);
+        let grazing = thin_film_reflectance(&film, 1.5, 0.5);
+        assert!((grazing.r - reflectance.r).abs() > 0.01);
+    }
+
+    #[test]
+    fn a_coat_without_strength_is_the_bare_base() {
+        let base = Lambertian {
+            albedo: Color::new(0.8, 0.1, 0.1),
+        };
+        let coat = ClearCoat {
+            base: Box::new(base.clone()),
+            strength: 0.0,
+            ior: 1.5,
+            roughness: 0.1,
+            thin_film: None,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(0.3, 1.0, 0.0).normalize();
+        let incoming = vector(-0.2, 1.0, 0.4).normalize();
+        assert_abs_diff_eq!(
+            coat.evaluate(&normal, &outgoing, &incoming),
+            base.evaluate(&normal, &outgoing, &incoming)
+        );
+    }
+
+    #[test]
+    fn a_coat_adds_a_white_highlight_to_a_colored_base() {
+        let coat = ClearCoat {
+            base: Box::new(Lambertian {
+                albedo: Color::new(0.8, 0.1, 0.1),
+            }),
+            strength: 1.0,
+            ior: 1.5,
+            roughness: 0.1,
+            thin_film: None,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(1.0, 1.0, 0.0).normalize();
+        let mirror = vector(-1.0, 1.0, 0.0).normalize();
+        let highlight = coat.evaluate(&normal, &outgoing, &mirror);
+        assert!(highlight.g > 1.0);
+        let reflected = reflectance(&coat, &normal, &outgoing);
+        assert!(reflected.r <= 1.01);
+        assert!(reflected.g > 0.1);
+    }
+
+    #[test]
+    fn coated_samples_agree_with_evaluate_and_pdf() {
+        assert_sample_is_consistent(&ClearCoat {
+            base: Box::new(Microfacet {
+                base_color: Color::new(0.2, 0.3, 0.8),
+                metallic: 0.8,
+                roughness: 0.5,
+            }),
+            strength: 0.8,
+            ior: 1.5,
+            roughness: 0.2,
+            thin_film: Some(ThinFilm {
+                thickness: 400.0,
+                ior: 1.4,
+            }),
+        });
+    }
+
+    #[test]
+    fn the_diffuse_part_is_whatever_is_not_glossy() {
+        let normal = vector(0.0, 0.0, 1.0);
+        let outgoing = vector(0.6, 0.0, 0.8);
+        let incoming = vector(-0.6, 0.0, 0.8);
+        let lambertian = Lambertian {
+            albedo: Color::new(0.5, 0.5, 0.5),
+        };
+        assert_eq!(
+            lambertian.evaluate_diffuse(&normal, &outgoing, &incoming),
+            lambertian.evaluate(&normal, &outgoing, &incoming)
+        );
+        let phong = Phong {
+            diffuse: Color::new(0.5, 0.5, 0.5),
+            specular: Color::new(0.5, 0.5, 0.5),
+            shininess: 20.0,
+        };
+        let diffuse = phong.evaluate_diffuse(&normal, &outgoing, &incoming);
+        assert_abs_diff_eq!(diffuse, multiply(&phong.diffuse, 1.0 / PI));
+        assert!(phong.evaluate(&normal, &outgoing, &incoming).r > diffuse.r);
+        let metal = Microfacet {
+            base_color: Color::new(0.9, 0.6, 0.2),
+            metallic: 1.0,
+            roughness: 0.3,
+        };
+        assert_eq!(
+            metal.evaluate_diffuse(&normal, &outgoing, &incoming),
+            Color::black()
+        );
+    }
+}
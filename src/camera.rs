@@ -0,0 +1,419 @@
+use crate::canvas::*;
+use crate::error::Error;
+use crate::rays::Ray;
+use crate::sampling::{uniform_sample_disk, uniform_sample_polygon};
+use crate::transformations::*;
+use crate::tuples::*;
+use crate::world::World;
+
+use std::f32::consts::PI;
+
+/// How directions around the camera are laid out on the image.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Projection {
+    /// Pinhole or thin lens; `field_of_view` spans the larger side.
+    Perspective,
+    /// Parallel rays covering `view_width` world units across the image.
+    Orthographic { view_width: f32 },
+    /// Equidistant fisheye: the angle from the view direction grows with the
+    /// distance to the center. `field_of_view` spans the larger side.
+    Fisheye,
+    /// Whole sphere, longitude across and latitude down, for 360° panoramas.
+    /// Images should be twice as wide as they are high.
+    Equirectangular,
+    /// Six 90° faces, in the order +x, -x, +y on the top row and -y, +z, -z
+    /// on the bottom one. Images should be three halves as wide as they are
+    /// high.
+    Cubemap,
+}
+
+/// Forward, right and up directions of the faces of a cubemap, in layout order.
+const CUBEMAP_FACES: [[[f32; 3]; 3]; 6] = [
+    [[1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]],
+    [[-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]],
+    [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]],
+    [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+    [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    [[0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+];
+
+/// Camera sitting at the origin and looking towards positive z. The
+/// transformation moves it from there into the world. With an `aperture` of
+/// 0 it is a pinhole camera and everything is in focus; otherwise it is a thin
+/// lens and only what is at `focal_distance` is sharp. The lens is only used
+/// by the perspective projection.
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub width: u32,
+    pub height: u32,
+    pub field_of_view: f32,
+    pub transformation: Transformation,
+    /// Radius of the lens.
+    pub aperture: f32,
+    /// Distance along the view direction of the plane in focus.
+    pub focal_distance: f32,
+    /// Number of diaphragm blades, giving polygonal bokeh. 0 means a round
+    /// diaphragm.
+    pub blades: u32,
+    pub projection: Projection,
+    /// Sideways distance from the camera to the eye rays start from, negative
+    /// for the left eye of a stereo pair. With the equirectangular projection
+    /// the eye turns around the camera with the view direction.
+    pub eye_offset: f32,
+    /// Distance at which the view of an offset eye crosses the one of the
+    /// camera. Infinity keeps both parallel.
+    pub convergence_distance: f32,
+}
+
+impl Camera {
+    pub fn new(width: u32, height: u32, field_of_view: f32) -> Camera {
+        Camera {
+            width,
+            height,
+            field_of_view,
+            transformation: identity(),
+            aperture: 0.0,
+            focal_distance: 1.0,
+            blades: 0,
+            projection: Projection::Perspective,
+            eye_offset: 0.0,
+            convergence_distance: f32::INFINITY,
+        }
+    }
+
+    fn half_sizes(self: &Self) -> (f32, f32) {
+        let half_view = (self.field_of_view / 2.0).tan();
+        let aspect = self.width as f32 / self.height as f32;
+        if aspect >= 1.0 {
+            (half_view, half_view / aspect)
+        } else {
+            (half_view * aspect, half_view)
+        }
+    }
+
+    pub fn pixel_size(self: &Self) -> f32 {
+        let (half_width, _) = self.half_sizes();
+        half_width * 2.0 / self.width as f32
+    }
+
+    /// Ray going through the center of the pixel.
+    pub fn ray_for_pixel(self: &Self, x: u32, y: u32) -> Ray {
+        self.ray_for_pixel_offset(x, y, 0.5, 0.5)
+    }
+
+    /// Ray going through a point of the pixel and the center of the lens.
+    /// Offsets go from 0 to 1 and (0, 0) is the top left corner of the pixel.
+    pub fn ray_for_pixel_offset(self: &Self, x: u32, y: u32, dx: f32, dy: f32) -> Ray {
+        self.ray_through_lens(x, y, dx, dy, 0.0, 0.0)
+    }
+
+    /// Ray going through a point of the pixel and a point of the lens, picked
+    /// by `lens_point` from two uniform numbers in [0, 1).
+    pub fn ray_for_lens_sample(
+        self: &Self,
+        x: u32,
+        y: u32,
+        pixel_offset: (f32, f32),
+        lens_sample: (f32, f32),
+    ) -> Ray {
+        let (lens_x, lens_y) = self.lens_point(lens_sample.0, lens_sample.1);
+        self.ray_through_lens(x, y, pixel_offset.0, pixel_offset.1, lens_x, lens_y)
+    }
+
+    /// Point of the lens, in camera space, with uniform density over the shape
+    /// of the diaphragm.
+    pub fn lens_point(self: &Self, u1: f32, u2: f32) -> (f32, f32) {
+        let (x, y) = if self.blades >= 3 {
+            uniform_sample_polygon(self.blades, u1, u2)
+        } else {
+            uniform_sample_disk(u1, u2)
+        };
+        (x * self.aperture, y * self.aperture)
+    }
+
+    fn ray_through_lens(
+        self: &Self,
+        x: u32,
+        y: u32,
+        dx: f32,
+        dy: f32,
+        lens_x: f32,
+        lens_y: f32,
+    ) -> Ray {
+        let image_x = (x as f32 + dx) / self.width as f32;
+        let image_y = (y as f32 + dy) / self.height as f32;
+        let ray = match self.projection {
+            Projection::Perspective => self.perspective_ray(x, y, dx, dy, lens_x, lens_y),
+            Projection::Orthographic { view_width } => {
+                let view_height = view_width * self.height as f32 / self.width as f32;
+                Ray {
+                    origin: point(
+                        (image_x - 0.5) * view_width + self.eye_offset,
+                        (0.5 - image_y) * view_height,
+                        0.0,
+                    ),
+                    direction: vector(-self.eye_offset / self.convergence_distance, 0.0, 1.0)
+                        .normalize(),
+                    time: 0.0,
+                }
+            }
+            Projection::Fisheye => {
+                let half_size = self.width.max(self.height) as f32 / 2.0;
+                let from_center_x = x as f32 + dx - self.width as f32 / 2.0;
+                let from_center_y = self.height as f32 / 2.0 - (y as f32 + dy);
+                let radius = (from_center_x * from_center_x + from_center_y * from_center_y).sqrt();
+                let theta = radius / half_size * self.field_of_view / 2.0;
+                let phi = from_center_y.atan2(from_center_x);
+                self.ray_from_center(
+                    vector(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        theta.cos(),
+                    ),
+                    vector(1.0, 0.0, 0.0),
+                )
+            }
+            Projection::Equirectangular => {
+                let longitude = (image_x - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - image_y) * PI;
+                // Omni-directional stereo: the eye stays to the side of
+                // whatever direction is looked at.
+                self.ray_from_center(
+                    vector(
+                        latitude.cos() * longitude.sin(),
+                        latitude.sin(),
+                        latitude.cos() * longitude.cos(),
+                    ),
+                    vector(longitude.cos(), 0.0, -longitude.sin()),
+                )
+            }
+            Projection::Cubemap => {
+                let column = ((image_x * 3.0) as usize).min(2);
+                let row = ((image_y * 2.0) as usize).min(1);
+                let across = (image_x * 3.0 - column as f32) * 2.0 - 1.0;
+                let up = 1.0 - (image_y * 2.0 - row as f32) * 2.0;
+                let [forward, right, top] = CUBEMAP_FACES[row * 3 + column];
+                self.ray_from_center(
+                    vector(
+                        forward[0] + across * right[0] + up * top[0],
+                        forward[1] + across * right[1] + up * top[1],
+                        forward[2] + across * right[2] + up * top[2],
+                    ),
+                    vector(1.0, 0.0, 0.0),
+                )
+            }
+        };
+        ray.transform(self.transformation.clone())
+    }
+
+    /// Ray looking along `direction` from the eye, which sits `eye_offset`
+    /// along `right` and turns in to meet the camera's view at the
+    /// convergence distance.
+    fn ray_from_center(self: &Self, direction: Tuple, right: Tuple) -> Ray {
+        let eye = mul(&right, self.eye_offset);
+        let toe_in = mul(&eye, 1.0 / self.convergence_distance);
+        Ray {
+            direction: sub(&direction.normalize(), &toe_in).normalize(),
+            origin: add(&point(0.0, 0.0, 0.0), &eye),
+            time: 0.0,
+        }
+    }
+
+    fn perspective_ray(
+        self: &Self,
+        x: u32,
+        y: u32,
+        dx: f32,
+        dy: f32,
+        lens_x: f32,
+        lens_y: f32,
+    ) -> Ray {
+        let (half_width, half_height) = self.half_sizes();
+        let pixel_size = self.pixel_size();
+        let camera_x = -half_width + (x as f32 + dx) * pixel_size;
+        let camera_y = half_height - (y as f32 + dy) * pixel_size;
+
+        // Every ray leaving the pixel meets the one through the center of the
+        // lens on the plane in focus. An offset eye shifts its image so that
+        // both views line up at the convergence distance.
+        let eye_x = self.eye_offset;
+        let shifted_x = camera_x - eye_x / self.convergence_distance;
+        let in_focus = point(
+            eye_x + shifted_x * self.focal_distance,
+            camera_y * self.focal_distance,
+            self.focal_distance,
+        );
+        let origin = point(eye_x + lens_x, lens_y, 0.0);
+        Ray {
+            direction: sub(&in_focus, &origin).normalize(),
+            origin,
+            time: 0.0,
+        }
+    }
+}
+
+/// Renders the world with the direct lighting model in `World::color_at`.
+/// Rays go through the center of the lens, so everything is in focus.
+pub fn render(camera: &Camera, world: &World) -> Result<Canvas, Error> {
+    world.check()?;
+    let mut canvas = build_canvas(camera.width, camera.height);
+    for y in 0..camera.height {
+        for x in 0..camera.width {
+            let ray = camera.ray_for_pixel(x, y);
+            write_pixel(&mut canvas, x, y, world.color_at(&ray));
+        }
+    }
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::*;
+
+    #[test]
+    fn pixel_size_of_a_horizontal_canvas() {
+        let camera = Camera::new(200, 125, PI / 2.0);
+        assert_abs_diff_eq!(camera.pixel_size(), 0.01);
+    }
+
+    #[test]
+    fn pixel_size_of_a_vertical_canvas() {
+        let camera = Camera::new(125, 200, PI / 2.0);
+        assert_abs_diff_eq!(camera.pixel_size(), 0.01);
+    }
+
+    #[test]
+    fn ray_through_the_center_of_the_canvas() {
+        let camera = Camera::new(201, 101, PI / 2.0);
+        let ray = camera.ray_for_pixel(100, 50);
+        assert_abs_diff_eq!(ray.origin, point(0.0, 0.0, 0.0));
+        assert_abs_diff_eq!(ray.direction, vector(0.0, 0.0, 1.0), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn ray_through_a_corner_of_the_canvas() {
+        let camera = Camera::new(201, 101, PI / 2.0);
+        let ray = camera.ray_for_pixel_offset(0, 0, 0.0, 0.0);
+        assert_abs_diff_eq!(
+            ray.direction,
+            vector(-1.0, 101.0 / 201.0, 1.0).normalize(),
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn ray_when_the_camera_is_transformed() {
+        let mut camera = Camera::new(201, 101, PI / 2.0);
+        camera.transformation = translation(0.0, 2.0, -5.0).dot(&rotate_y(PI / 4.0));
+        let ray = camera.ray_for_pixel(100, 50);
+        assert_abs_diff_eq!(ray.origin, point(0.0, 2.0, -5.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(
+            ray.direction,
+            vector(2.0_f32.sqrt() / 2.0, 0.0, 2.0_f32.sqrt() / 2.0),
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn a_camera_without_aperture_is_a_pinhole() {
+        let mut camera = Camera::new(201, 101, PI / 2.0);
+        camera.focal_distance = 3.0;
+        let pinhole = camera.ray_for_pixel_offset(20, 30, 0.2, 0.7);
+        let lens = camera.ray_for_lens_sample(20, 30, (0.2, 0.7), (0.9, 0.4));
+        assert_abs_diff_eq!(lens.origin, pinhole.origin);
+        assert_abs_diff_eq!(lens.direction, pinhole.direction, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn rays_through_the_lens_meet_on_the_plane_in_focus() {
+        let mut camera = Camera::new(201, 101, PI / 2.0);
+        camera.aperture = 0.5;
+        camera.focal_distance = 4.0;
+        let center = camera.ray_for_pixel(30, 60);
+        let in_focus = center.position(4.0 / center.direction.z());
+        for (u1, u2) in [(0.1, 0.2), (0.9, 0.5), (0.5, 0.99)].iter() {
+            let ray = camera.ray_for_lens_sample(30, 60, (0.5, 0.5), (*u1, *u2));
+            assert_abs_diff_eq!(ray.origin.z(), 0.0);
+            assert!(magnitude(&ray.origin) > 0.0);
+            let t = (in_focus.z() - ray.origin.z()) / ray.direction.z();
+            assert_abs_diff_eq!(ray.position(t), in_focus, epsilon = 0.0001);
+        }
+    }
+
+    #[test]
+    fn lens_samples_stay_inside_the_aperture() {
+        let mut camera = Camera::new(10, 10, PI / 2.0);
+        camera.aperture = 0.25;
+        for blades in [0, 5].iter() {
+            camera.blades = *blades;
+            for i in 0..10 {
+                let (x, y) = camera.lens_point(i as f32 / 10.0, 0.37);
+                assert!((x * x + y * y).sqrt() <= 0.25 + 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let mut camera = Camera::new(200, 100, PI / 2.0);
+        camera.projection = Projection::Orthographic { view_width: 4.0 };
+        let corner = camera.ray_for_pixel_offset(0, 0, 0.0, 0.0);
+        let center = camera.ray_for_pixel_offset(100, 50, 0.0, 0.0);
+        assert_abs_diff_eq!(corner.origin, point(-2.0, 1.0, 0.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(center.origin, point(0.0, 0.0, 0.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(corner.direction, vector(0.0, 0.0, 1.0));
+        assert_abs_diff_eq!(center.direction, vector(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn fisheye_angles_grow_with_the_distance_to_the_center() {
+        let mut camera = Camera::new(100, 100, PI);
+        camera.projection = Projection::Fisheye;
+        let center = camera.ray_for_pixel_offset(50, 50, 0.0, 0.0);
+        assert_abs_diff_eq!(center.direction, vector(0.0, 0.0, 1.0), epsilon = 0.0001);
+        // Half a field of view of 180° at the edge looks sideways.
+        let edge = camera.ray_for_pixel_offset(100, 50, 0.0, 0.0);
+        assert_abs_diff_eq!(edge.direction, vector(1.0, 0.0, 0.0), epsilon = 0.0001);
+        let halfway = camera.ray_for_pixel_offset(50, 25, 0.0, 0.0);
+        let half = 2.0_f32.sqrt() / 2.0;
+        assert_abs_diff_eq!(halfway.direction, vector(0.0, half, half), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn equirectangular_images_cover_the_whole_sphere() {
+        let mut camera = Camera::new(200, 100, PI / 2.0);
+        camera.projection = Projection::Equirectangular;
+        let direction = |x: u32, y: u32| camera.ray_for_pixel_offset(x, y, 0.0, 0.0).direction;
+        assert_abs_diff_eq!(direction(100, 50), vector(0.0, 0.0, 1.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(direction(150, 50), vector(1.0, 0.0, 0.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(direction(0, 50), vector(0.0, 0.0, -1.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(direction(100, 0), vector(0.0, 1.0, 0.0), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn each_cubemap_face_looks_along_an_axis() {
+        let mut camera = Camera::new(300, 200, PI / 2.0);
+        camera.projection = Projection::Cubemap;
+        let expected = [
+            vector(1.0, 0.0, 0.0),
+            vector(-1.0, 0.0, 0.0),
+            vector(0.0, 1.0, 0.0),
+            vector(0.0, -1.0, 0.0),
+            vector(0.0, 0.0, 1.0),
+            vector(0.0, 0.0, -1.0),
+        ];
+        for (face, direction) in expected.iter().enumerate() {
+            let x = (face % 3) as u32 * 100 + 50;
+            let y = (face / 3) as u32 * 100 + 50;
+            let ray = camera.ray_for_pixel_offset(x, y, 0.0, 0.0);
+            assert_abs_diff_eq!(ray.direction, *direction, epsilon = 0.0001);
+        }
+        // Faces cover 90° each; this is the top left corner of +z.
+        let corner = camera.ray_for_pixel_offset(100, 100, 0.0, 0.0).direction;
+        assert_abs_diff_eq!(
+            corner,
+            vector(-1.0, 1.0, 1.0).normalize(),
+            epsilon = 0.0001
+        );
+    }
+}
@@ -1,8 +1,14 @@
 extern crate image;
 
-use image::{ImageBuffer, RgbImage};
+use std::fs;
 
+use crc32fast::Hasher;
+use image::png::PNGEncoder;
+use image::ColorType;
+
+use crate::color_spaces::ColorSpace;
 use crate::colors::Color;
+use crate::error::Error;
 
 #[derive(Debug)]
 pub struct Canvas {
@@ -11,6 +17,16 @@ pub struct Canvas {
     data: Vec<Vec<Color>>,
 }
 
+impl Canvas {
+    pub fn width(self: &Self) -> u32 {
+        self.width
+    }
+
+    pub fn height(self: &Self) -> u32 {
+        self.height
+    }
+}
+
 pub fn build_canvas(width: u32, height: u32) -> Canvas {
     Canvas {
         width: width,
@@ -29,7 +45,7 @@ pub fn build_canvas(width: u32, height: u32) -> Canvas {
     }
 }
 
-fn pixel_at(canvas: &Canvas, x: u32, y: u32) -> Color {
+pub fn pixel_at(canvas: &Canvas, x: u32, y: u32) -> Color {
     canvas.data[y as usize][x as usize].clone()
 }
 
@@ -37,23 +53,65 @@ pub fn write_pixel(canvas: &mut Canvas, x: u32, y: u32, color: Color) {
     canvas.data[y as usize][x as usize] = color.clone();
 }
 
-pub fn export_png(canvas: &Canvas, filename: &str) {
-    let width = canvas.width.clone();
-    let height = canvas.height.clone();
-    let mut img: RgbImage = ImageBuffer::new(width, height);
-
-    for i in 0..width {
-        for j in 0..height {
-            let pixel = pixel_at(canvas, i, j);
-            let r = (255.0 * pixel.r) as u8;
-            let g = (255.0 * pixel.g) as u8;
-            let b = (255.0 * pixel.b) as u8;
+/// Copies `image` into `canvas` with its top left corner at (`left`, `top`).
+/// Pixels falling outside of `canvas` are dropped.
+pub fn paste(canvas: &mut Canvas, image: &Canvas, left: u32, top: u32) {
+    for y in 0..image.height.min(canvas.height.saturating_sub(top)) {
+        for x in 0..image.width.min(canvas.width.saturating_sub(left)) {
+            write_pixel(canvas, left + x, top + y, pixel_at(image, x, y));
+        }
+    }
+}
 
-            img.put_pixel(i, j, image::Rgb([r, g, b]));
+/// Saves `canvas` as a PNG image encoded in `color_space`, which is
+/// recorded in the file for viewers.
+pub fn export_png(canvas: &Canvas, filename: &str, color_space: ColorSpace) -> Result<(), Error> {
+    let width = canvas.width;
+    let height = canvas.height;
+    let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+    for j in 0..height {
+        for i in 0..width {
+            let pixel = color_space.encode(&pixel_at(canvas, i, j));
+            pixels.extend(&[to_byte(pixel.r), to_byte(pixel.g), to_byte(pixel.b)]);
         }
     }
 
-    img.save(filename).unwrap();
+    let mut png = vec![];
+    PNGEncoder::new(&mut png)
+        .encode(&pixels, width, height, ColorType::Rgb8)
+        .map_err(|source| Error::Image {
+            filename: filename.to_string(),
+            source,
+        })?;
+    let png = with_chunks(&png, &color_space.png_chunks());
+    fs::write(filename, png).map_err(|source| Error::Io {
+        filename: filename.to_string(),
+        source,
+    })
+}
+
+/// Channel of a color in 0..1 as a byte, rounded and clamped.
+pub fn to_byte(channel: f32) -> u8 {
+    (channel * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Inserts `chunks` in `png` right after its header chunk, where the ones
+/// describing colors must be.
+fn with_chunks(png: &[u8], chunks: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    // 8 bytes of signature, then IHDR: length, name, 13 bytes and the CRC.
+    let header_end = 8 + 4 + 4 + 13 + 4;
+    let mut result = png[..header_end].to_vec();
+    for (name, data) in chunks {
+        result.extend(&(data.len() as u32).to_be_bytes());
+        let mut hasher = Hasher::new();
+        hasher.update(name);
+        hasher.update(data);
+        result.extend(name);
+        result.extend(data);
+        result.extend(&hasher.finalize().to_be_bytes());
+    }
+    result.extend(&png[header_end..]);
+    result
 }
 
 #[cfg(test)]
@@ -91,4 +149,34 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn pasting_an_image_moves_its_pixels() {
+        let red = Color {
+            r: 1.0,
+            g: 0.0,
+            b: 0.0,
+        };
+        let mut image = build_canvas(2, 2);
+        write_pixel(&mut image, 1, 1, red.clone());
+        let mut canvas = build_canvas(4, 3);
+        paste(&mut canvas, &image, 2, 1);
+        assert_eq!(pixel_at(&canvas, 2, 1), Color::black());
+        assert_eq!(pixel_at(&canvas, 3, 2), red);
+        // Whatever falls outside of the canvas is dropped.
+        paste(&mut canvas, &image, 3, 2);
+        assert_eq!(pixel_at(&canvas, 3, 2), Color::black());
+    }
+
+    #[test]
+    fn png_images_are_encoded_in_their_color_space() {
+        let mut canvas = build_canvas(2, 1);
+        write_pixel(&mut canvas, 0, 0, Color::new(0.22, 1.0, 0.0));
+        let filename =
+            std::env::temp_dir().join(format!("alart-canvas-{}.png", std::process::id()));
+        export_png(&canvas, filename.to_str().unwrap(), ColorSpace::Srgb).unwrap();
+        let image = image::open(&filename).unwrap().to_rgb();
+        assert_eq!(image.get_pixel(0, 0).0, [129, 255, 0]);
+        fs::remove_file(&filename).unwrap();
+    }
 }
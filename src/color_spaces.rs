@@ -0,0 +1,238 @@
+//! Color spaces of 8 bit images. Renders are linear with the primaries of
+//! sRGB and Rec.709, but displays expect values encoded with a transfer
+//! curve, and wide gamut ones other primaries. Encoding converts a linear
+//! color to the space and decoding brings it back.
+
+use std::str::FromStr;
+
+use crate::canvas::*;
+use crate::colors::Color;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ColorSpace {
+    /// The space of the web and of most images.
+    Srgb,
+    /// The space of HD video, with the sRGB primaries and its own curve.
+    Rec709,
+    /// The wider gamut of recent screens, with the sRGB curve.
+    DisplayP3,
+    /// No transfer curve, for data like normals or depth.
+    Linear,
+}
+
+/// Linear sRGB to linear Display P3, both with a D65 white.
+const SRGB_TO_P3: [[f32; 3]; 3] = [
+    [0.822_462, 0.177_538, 0.0],
+    [0.033_194, 0.966_806, 0.0],
+    [0.017_083, 0.072_397, 0.910_520],
+];
+
+const P3_TO_SRGB: [[f32; 3]; 3] = [
+    [1.224_94, -0.224_94, 0.0],
+    [-0.042_057, 1.042_057, 0.0],
+    [-0.019_638, -0.078_636, 1.098_273],
+];
+
+impl ColorSpace {
+    /// Color in this space of the linear `color`, clamped to 0..1.
+    pub fn encode(self: &Self, color: &Color) -> Color {
+        let color = match self {
+            ColorSpace::DisplayP3 => convert(&SRGB_TO_P3, color),
+            _ => color.clone(),
+        };
+        let transfer = |x: f32| {
+            let x = x.clamp(0.0, 1.0);
+            match self {
+                ColorSpace::Srgb | ColorSpace::DisplayP3 => srgb_encode(x),
+                ColorSpace::Rec709 => rec709_encode(x),
+                ColorSpace::Linear => x,
+            }
+        };
+        Color::new(transfer(color.r), transfer(color.g), transfer(color.b))
+    }
+
+    /// Linear color of `color`, given in this space, like the texels of an
+    /// image read from disk.
+    pub fn decode(self: &Self, color: &Color) -> Color {
+        let transfer = |x: f32| match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => srgb_decode(x),
+            ColorSpace::Rec709 => rec709_decode(x),
+            ColorSpace::Linear => x,
+        };
+        let color = Color::new(transfer(color.r), transfer(color.g), transfer(color.b));
+        match self {
+            ColorSpace::DisplayP3 => convert(&P3_TO_SRGB, &color),
+            _ => color,
+        }
+    }
+
+    pub fn encode_canvas(self: &Self, canvas: &Canvas) -> Canvas {
+        map_canvas(canvas, |color| self.encode(color))
+    }
+
+    pub fn decode_canvas(self: &Self, canvas: &Canvas) -> Canvas {
+        map_canvas(canvas, |color| self.decode(color))
+    }
+
+    /// PNG chunks telling viewers the space of the image: cICP for the recent
+    /// ones, and cHRM, gAMA and sRGB for the others.
+    pub fn png_chunks(self: &Self) -> Vec<([u8; 4], Vec<u8>)> {
+        // Coding points of ITU-T H.273, and the chromaticities of the
+        // white, red, green and blue points times 100000.
+        let (primaries, transfer, chromaticities) = match self {
+            ColorSpace::Srgb => (1, 13, SRGB_CHROMATICITIES),
+            ColorSpace::Rec709 => (1, 1, SRGB_CHROMATICITIES),
+            ColorSpace::DisplayP3 => (12, 13, P3_CHROMATICITIES),
+            ColorSpace::Linear => (1, 8, SRGB_CHROMATICITIES),
+        };
+        let mut chunks = vec![
+            (*b"cICP", vec![primaries, transfer, 0, 1]),
+            (
+                *b"cHRM",
+                chromaticities
+                    .iter()
+                    .flat_map(|value: &u32| value.to_be_bytes().to_vec())
+                    .collect(),
+            ),
+        ];
+        match self {
+            ColorSpace::Srgb => {
+                chunks.push((*b"gAMA", 45455_u32.to_be_bytes().to_vec()));
+                // Perceptual rendering intent.
+                chunks.push((*b"sRGB", vec![0]));
+            }
+            ColorSpace::DisplayP3 => chunks.push((*b"gAMA", 45455_u32.to_be_bytes().to_vec())),
+            ColorSpace::Linear => chunks.push((*b"gAMA", 100000_u32.to_be_bytes().to_vec())),
+            ColorSpace::Rec709 => {}
+        }
+        chunks
+    }
+}
+
+const SRGB_CHROMATICITIES: [u32; 8] = [31270, 32900, 64000, 33000, 30000, 60000, 15000, 6000];
+const P3_CHROMATICITIES: [u32; 8] = [31270, 32900, 68000, 32000, 26500, 69000, 15000, 6000];
+
+/// Names for the command line.
+impl FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ColorSpace, String> {
+        match name {
+            "srgb" => Ok(ColorSpace::Srgb),
+            "rec709" => Ok(ColorSpace::Rec709),
+            "display-p3" => Ok(ColorSpace::DisplayP3),
+            "linear" => Ok(ColorSpace::Linear),
+            _ => Err(format!(
+                "unknown color space '{}', expected srgb, rec709, display-p3 or linear",
+                name
+            )),
+        }
+    }
+}
+
+pub fn srgb_encode(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_decode(x: f32) -> f32 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn rec709_encode(x: f32) -> f32 {
+    if x < 0.018 {
+        4.5 * x
+    } else {
+        1.099 * x.powf(0.45) - 0.099
+    }
+}
+
+fn rec709_decode(x: f32) -> f32 {
+    if x < 0.081 {
+        x / 4.5
+    } else {
+        ((x + 0.099) / 1.099).powf(1.0 / 0.45)
+    }
+}
+
+fn convert(matrix: &[[f32; 3]; 3], color: &Color) -> Color {
+    let row = |i: usize| matrix[i][0] * color.r + matrix[i][1] * color.g + matrix[i][2] * color.b;
+    Color::new(row(0), row(1), row(2))
+}
+
+fn map_canvas<F: Fn(&Color) -> Color>(canvas: &Canvas, map: F) -> Canvas {
+    let mut mapped = build_canvas(canvas.width(), canvas.height());
+    for y in 0..canvas.height() {
+        for x in 0..canvas.width() {
+            write_pixel(&mut mapped, x, y, map(&pixel_at(canvas, x, y)));
+        }
+    }
+    mapped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color_spaces::*;
+
+    const SPACES: [ColorSpace; 4] = [
+        ColorSpace::Srgb,
+        ColorSpace::Rec709,
+        ColorSpace::DisplayP3,
+        ColorSpace::Linear,
+    ];
+
+    #[test]
+    fn srgb_brightens_the_midtones() {
+        assert_abs_diff_eq!(srgb_encode(0.0), 0.0);
+        assert_abs_diff_eq!(srgb_encode(1.0), 1.0, epsilon = 0.0001);
+        assert_abs_diff_eq!(srgb_encode(0.214), 0.5, epsilon = 0.001);
+        assert_abs_diff_eq!(srgb_encode(0.001), 0.01292, epsilon = 0.00001);
+        let encoded = ColorSpace::Srgb.encode(&Color::new(0.214, 2.0, -1.0));
+        assert_abs_diff_eq!(encoded, Color::new(0.5, 1.0, 0.0), epsilon = 0.001);
+    }
+
+    #[test]
+    fn decoding_undoes_encoding() {
+        let color = Color::new(0.02, 0.3, 0.9);
+        for space in SPACES.iter() {
+            let decoded = space.decode(&space.encode(&color));
+            assert_abs_diff_eq!(decoded, color, epsilon = 0.0001);
+        }
+    }
+
+    #[test]
+    fn display_p3_has_a_wider_gamut() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        assert_abs_diff_eq!(
+            ColorSpace::DisplayP3.encode(&white),
+            white,
+            epsilon = 0.0001
+        );
+        // Pure sRGB red is inside P3, so it has some green and blue there.
+        let red = ColorSpace::DisplayP3.encode(&Color::new(1.0, 0.0, 0.0));
+        assert!(red.r < 1.0 && red.g > 0.0 && red.b > 0.0);
+    }
+
+    #[test]
+    fn png_chunks_name_the_space() {
+        let chunks = ColorSpace::DisplayP3.png_chunks();
+        assert_eq!(chunks[0], (*b"cICP", vec![12, 13, 0, 1]));
+        assert_eq!(chunks[1].0, *b"cHRM");
+        assert_eq!(chunks[1].1.len(), 32);
+        let names: Vec<[u8; 4]> = ColorSpace::Srgb
+            .png_chunks()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec![*b"cICP", *b"cHRM", *b"gAMA", *b"sRGB"]);
+        assert_eq!("display-p3".parse(), Ok(ColorSpace::DisplayP3));
+        assert!("adobe-rgb".parse::<ColorSpace>().is_err());
+    }
+}
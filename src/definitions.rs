@@ -0,0 +1,513 @@
+//! Reuse in scene files. A scene can `include` other files and `define`
+//! named pieces that are used anywhere a value goes:
+//!
+//! ```yaml
+//! include:
+//!   - materials.yaml
+//! define:
+//!   glass:
+//!     model: { type: rough_dielectric, ior: 1.5 }
+//!   green_glass:
+//!     extend: glass
+//!     color: [0.6, 1, 0.6]
+//!   on_floor:
+//!     - [translation, 0, 1, 0]
+//!   ball:
+//!     parameters: { x: 0, material: glass }
+//!     body:
+//!       type: sphere
+//!       transform: [[translation, $x, 0, 0], on_floor]
+//!       material: $material
+//! objects:
+//!   - template: ball
+//!     x: 2
+//!     material: green_glass
+//! ```
+//!
+//! A definition is used by writing its name as a value, and `extend` copies a
+//! defined mapping and changes some of its entries. Definitions with
+//! `parameters` are templates: `template` makes a copy of their `body` where
+//! every `$parameter` is replaced by the value given next to it, or by its
+//! default. Parameters listed without defaults must be given.
+//!
+//! Included files are relative to the file including them. Their definitions,
+//! lights and objects come before the ones of the including file, and later
+//! definitions replace earlier ones with the same name. Names of definitions
+//! shouldn't be the same as the names of types or models.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::scene::{read_file, SceneError};
+use crate::yaml::{Node, Value};
+
+/// Sections of scene files holding lists that add up across includes.
+const LISTS: [&str; 2] = ["lights", "objects"];
+
+/// Reads the includes of `root`, a scene read from `folder`, and replaces
+/// every use of a definition by what it stands for.
+pub fn expand(root: Node, folder: &Path) -> Result<Node, SceneError> {
+    let mut definitions = HashMap::new();
+    let root = gather(root, folder, &mut definitions, &mut vec![])?;
+    Expander {
+        definitions,
+        using: vec![],
+    }
+    .expand(&root, None)
+}
+
+/// Merges the included files into `root`, minus their definitions that go
+/// into `definitions`. `including` holds the files being read, to find
+/// includes going in circles.
+fn gather(
+    root: Node,
+    folder: &Path,
+    definitions: &mut HashMap<String, Node>,
+    including: &mut Vec<PathBuf>,
+) -> Result<Node, SceneError> {
+    let entries = match root.value {
+        Value::Map(entries) => entries,
+        _ => return Ok(root),
+    };
+    let mut merged: Vec<(String, Node)> = vec![];
+    let mut own = vec![];
+    for (key, node) in entries {
+        match key.as_str() {
+            "include" => {
+                let files = match &node.value {
+                    Value::List(files) => files.clone(),
+                    _ => vec![node.clone()],
+                };
+                for file in files.iter() {
+                    let included = include(file, folder, definitions, including)?;
+                    if let Value::Map(entries) = included.value {
+                        for (key, node) in entries {
+                            merge(&mut merged, key, node);
+                        }
+                    }
+                }
+            }
+            "define" => match node.value {
+                Value::Map(entries) => definitions.extend(entries),
+                _ => return Err(SceneError::at(&node, "expected a mapping".to_string())),
+            },
+            _ => own.push((key, node)),
+        }
+    }
+    for (key, node) in own {
+        merge(&mut merged, key, node);
+    }
+    Ok(Node {
+        value: Value::Map(merged),
+        ..root
+    })
+}
+
+fn include(
+    file: &Node,
+    folder: &Path,
+    definitions: &mut HashMap<String, Node>,
+    including: &mut Vec<PathBuf>,
+) -> Result<Node, SceneError> {
+    let filename = match &file.value {
+        Value::String(filename) => folder.join(filename),
+        _ => return Err(SceneError::at(file, "expected a file name".to_string())),
+    };
+    let canonical = fs::canonicalize(&filename).unwrap_or_else(|_| filename.clone());
+    if including.contains(&canonical) {
+        return Err(SceneError::at(
+            file,
+            format!("{} includes itself", filename.display()),
+        ));
+    }
+    let root = read_file(&filename)?;
+    including.push(canonical);
+    let folder = filename.parent().unwrap_or_else(|| Path::new(""));
+    let included = gather(root, folder, definitions, including);
+    including.pop();
+    included
+}
+
+/// Adds a top level entry, appending to lists of lights and objects and
+/// replacing anything else.
+fn merge(entries: &mut Vec<(String, Node)>, key: String, node: Node) {
+    match entries.iter_mut().find(|(name, _)| *name == key) {
+        Some((_, existing)) => match (&mut existing.value, node.value) {
+            (Value::List(items), Value::List(more)) if LISTS.contains(&key.as_str()) => {
+                items.extend(more)
+            }
+            (_, value) => *existing = Node { value, ..node },
+        },
+        None => entries.push((key, node)),
+    }
+}
+
+struct Expander {
+    definitions: HashMap<String, Node>,
+    /// Definitions being expanded, to find the ones using themselves.
+    using: Vec<String>,
+}
+
+impl Expander {
+    /// Expands `node`, the value of `key` in its mapping if it has one.
+    fn expand(self: &mut Self, node: &Node, key: Option<&str>) -> Result<Node, SceneError> {
+        match &node.value {
+            // Types name built-in things, never definitions.
+            Value::String(name) if key != Some("type") && self.definitions.contains_key(name) => {
+                self.definition(node, name)
+            }
+            Value::List(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.expand(item, None))
+                    .collect::<Result<Vec<Node>, SceneError>>()?;
+                Ok(Node {
+                    value: Value::List(items),
+                    ..node.clone()
+                })
+            }
+            Value::Map(entries) => {
+                if let Some(template) = node.get("template") {
+                    return self.instantiate(node, template);
+                }
+                let mut expanded = match node.get("extend") {
+                    Some(base) => match (&base.value, self.expand(base, None)?.value) {
+                        (_, Value::Map(entries)) => entries,
+                        (Value::String(name), _) if !self.definitions.contains_key(name) => {
+                            return Err(SceneError::at(
+                                base,
+                                format!("unknown definition '{}'", name),
+                            ))
+                        }
+                        _ => return Err(SceneError::at(base, "expected a mapping".to_string())),
+                    },
+                    None => vec![],
+                };
+                for (key, value) in entries.iter().filter(|(key, _)| key != "extend") {
+                    let value = self.expand(value, Some(key))?;
+                    match expanded.iter_mut().find(|(name, _)| name == key) {
+                        Some((_, existing)) => *existing = value,
+                        None => expanded.push((key.clone(), value)),
+                    }
+                }
+                Ok(Node {
+                    value: Value::Map(expanded),
+                    ..node.clone()
+                })
+            }
+            _ => Ok(node.clone()),
+        }
+    }
+
+    /// Expanded copy of the definition `name`, used at `node`.
+    fn definition(self: &mut Self, node: &Node, name: &str) -> Result<Node, SceneError> {
+        let definition = self.definitions[name].clone();
+        if definition.get("parameters").is_some() {
+            return Err(SceneError::at(
+                node,
+                format!("'{}' is a template, it is used with `template`", name),
+            ));
+        }
+        self.using_definition(node, name, |expander| expander.expand(&definition, None))
+    }
+
+    fn instantiate(self: &mut Self, node: &Node, template: &Node) -> Result<Node, SceneError> {
+        let name = match &template.value {
+            Value::String(name) => name.clone(),
+            _ => return Err(SceneError::at(template, "expected a name".to_string())),
+        };
+        let definition = match self.definitions.get(&name) {
+            Some(definition) if definition.get("parameters").is_some() => definition.clone(),
+            Some(_) => {
+                return Err(SceneError::at(
+                    template,
+                    format!("'{}' is not a template", name),
+                ))
+            }
+            None => {
+                return Err(SceneError::at(
+                    template,
+                    format!("unknown template '{}'", name),
+                ))
+            }
+        };
+        let parameters = parameters(definition.get("parameters").unwrap())?;
+        let mut arguments = HashMap::new();
+        if let Value::Map(entries) = &node.value {
+            for (key, value) in entries.iter().filter(|(key, _)| key != "template") {
+                if !parameters.iter().any(|(parameter, _)| parameter == key) {
+                    return Err(SceneError::at(
+                        value,
+                        format!("template '{}' has no parameter '{}'", name, key),
+                    ));
+                }
+                arguments.insert(key.clone(), self.expand(value, Some(key))?);
+            }
+        }
+        for (parameter, default) in parameters {
+            if arguments.contains_key(&parameter) {
+                continue;
+            }
+            match default {
+                Some(default) => {
+                    let default = self.expand(&default, None)?;
+                    arguments.insert(parameter, default);
+                }
+                None => {
+                    return Err(SceneError::at(
+                        node,
+                        format!("template '{}' needs a value for '{}'", name, parameter),
+                    ))
+                }
+            }
+        }
+        let body = match definition.get("body") {
+            Some(body) => substitute(body, &arguments)?,
+            None => return Err(SceneError::at(&definition, "missing 'body'".to_string())),
+        };
+        self.using_definition(node, &name, |expander| expander.expand(&body, None))
+    }
+
+    fn using_definition<F: FnOnce(&mut Expander) -> Result<Node, SceneError>>(
+        self: &mut Self,
+        node: &Node,
+        name: &str,
+        expand: F,
+    ) -> Result<Node, SceneError> {
+        if self.using.iter().any(|using| using == name) {
+            return Err(SceneError::at(
+                node,
+                format!("'{}' is defined in terms of itself", name),
+            ));
+        }
+        self.using.push(name.to_string());
+        let expanded = expand(self);
+        self.using.pop();
+        expanded
+    }
+}
+
+/// Names of the parameters of a template along with their defaults. They are
+/// either a list of names, or a mapping from names to defaults.
+fn parameters(node: &Node) -> Result<Vec<(String, Option<Node>)>, SceneError> {
+    match &node.value {
+        Value::List(names) => names
+            .iter()
+            .map(|name| match &name.value {
+                Value::String(name) => Ok((name.clone(), None)),
+                _ => Err(SceneError::at(name, "expected a name".to_string())),
+            })
+            .collect(),
+        Value::Map(entries) => Ok(entries
+            .iter()
+            .map(|(name, default)| (name.clone(), Some(default.clone())))
+            .collect()),
+        _ => Err(SceneError::at(
+            node,
+            "expected a list or a mapping".to_string(),
+        )),
+    }
+}
+
+/// Copy of `node` with every `$parameter` replaced by its argument.
+fn substitute(node: &Node, arguments: &HashMap<String, Node>) -> Result<Node, SceneError> {
+    let value = match &node.value {
+        Value::String(text) if text.starts_with('$') => {
+            return arguments
+                .get(&text[1..])
+                .cloned()
+                .ok_or_else(|| SceneError::at(node, format!("unknown parameter '{}'", &text[1..])))
+        }
+        Value::List(items) => Value::List(
+            items
+                .iter()
+                .map(|item| substitute(item, arguments))
+                .collect::<Result<Vec<Node>, SceneError>>()?,
+        ),
+        Value::Map(entries) => Value::Map(
+            entries
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), substitute(value, arguments)?)))
+                .collect::<Result<Vec<(String, Node)>, SceneError>>()?,
+        ),
+        other => other.clone(),
+    };
+    Ok(Node {
+        value,
+        ..node.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::definitions::*;
+    use crate::scene::parse_scene;
+    use crate::yaml::parse;
+
+    use crate::colors::Color;
+    use crate::error::Error;
+    use crate::materials::BsdfModel;
+    use crate::transformations::*;
+
+    use std::env;
+
+    fn expanded(source: &str) -> Result<Node, SceneError> {
+        expand(parse(source, None).unwrap(), Path::new(""))
+    }
+
+    #[test]
+    fn names_of_definitions_stand_for_them() {
+        let node = expanded(
+            "
+define:
+  red: [1, 0, 0]
+  paint: { color: red }
+material: paint
+",
+        )
+        .unwrap();
+        assert_eq!(node.get("define"), None);
+        let color = node.get("material").unwrap().get("color").unwrap();
+        // Errors in a definition point at where it is defined.
+        assert_eq!(color.line, 3);
+        match &color.value {
+            Value::List(items) => assert_eq!(
+                items.iter().map(|item| &item.value).collect::<Vec<_>>(),
+                vec![
+                    &Value::Number(1.0),
+                    &Value::Number(0.0),
+                    &Value::Number(0.0)
+                ]
+            ),
+            other => panic!("Expected a list, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn extending_replaces_some_entries() {
+        let scene = parse_scene(
+            "
+define:
+  glass: { color: [1, 1, 1], model: { type: rough_dielectric, ior: 1.5 } }
+  green_glass: { extend: glass, color: [0.5, 1, 0.5] }
+camera: { width: 1, height: 1 }
+objects:
+  - { type: sphere, material: glass }
+  - { type: sphere, material: green_glass }
+  - type: sphere
+    material: { extend: green_glass, model: lambertian }
+",
+        )
+        .unwrap();
+        let materials: Vec<_> = scene
+            .world
+            .objects
+            .iter()
+            .map(|object| object.material.clone())
+            .collect();
+        assert_eq!(materials[0].color, Color::new(1.0, 1.0, 1.0));
+        assert_eq!(materials[1].color, Color::new(0.5, 1.0, 0.5));
+        assert_eq!(materials[1].model, materials[0].model);
+        assert_eq!(materials[2].color, Color::new(0.5, 1.0, 0.5));
+        assert_eq!(materials[2].model, BsdfModel::Lambertian);
+    }
+
+    #[test]
+    fn defined_transforms_are_spliced_in() {
+        let scene = parse_scene(
+            "
+define:
+  lift: [[translation, 0, 1, 0]]
+camera: { width: 1, height: 1 }
+objects:
+  - type: sphere
+    transform: [[scale, 2, 2, 2], lift]
+",
+        )
+        .unwrap();
+        assert_abs_diff_eq!(
+            scene.world.objects[0].transformation,
+            translation(0.0, 1.0, 0.0).dot(&scale(2.0, 2.0, 2.0))
+        );
+    }
+
+    #[test]
+    fn templates_replace_their_parameters() {
+        let scene = parse_scene(
+            "
+define:
+  ball:
+    parameters: { x: 0, color: [1, 1, 1] }
+    body:
+      type: sphere
+      transform: [[translation, $x, 0, 0]]
+      material: { color: $color }
+camera: { width: 1, height: 1 }
+objects:
+  - { template: ball, x: 2 }
+  - { template: ball, color: [1, 0, 0] }
+",
+        )
+        .unwrap();
+        let objects = &scene.world.objects;
+        assert_abs_diff_eq!(objects[0].transformation, translation(2.0, 0.0, 0.0));
+        assert_eq!(objects[0].material.color, Color::new(1.0, 1.0, 1.0));
+        assert_abs_diff_eq!(objects[1].transformation, identity());
+        assert_eq!(objects[1].material.color, Color::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn templates_check_their_arguments() {
+        let source = "
+define:
+  ball: { parameters: [x], body: { type: sphere } }
+objects:
+  - { template: ball }
+  - { template: ball, x: 1, y: 2 }
+";
+        let error = expanded(source).unwrap_err();
+        assert_eq!(error.message, "template 'ball' needs a value for 'x'");
+        assert_eq!(error.line, Some(5));
+        let error = expanded(&source.replace("  - { template: ball }\n", "")).unwrap_err();
+        assert_eq!(error.message, "template 'ball' has no parameter 'y'");
+    }
+
+    #[test]
+    fn definitions_cannot_use_themselves() {
+        let error = expanded("define: { a: { extend: b }, b: { extend: a } }\nuse: a").unwrap_err();
+        assert!(error.message.contains("in terms of itself"));
+    }
+
+    #[test]
+    fn included_files_bring_their_definitions_and_objects() {
+        let folder = env::temp_dir().join(format!("alart-includes-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(
+            folder.join("library.yaml"),
+            "define: { red: { color: [1, 0, 0] } }\nobjects: [{ type: sphere }]",
+        )
+        .unwrap();
+        fs::write(
+            folder.join("scene.yaml"),
+            "include: library.yaml\ncamera: { width: 1, height: 1 }\nobjects: [{ type: sphere, material: red }]",
+        )
+        .unwrap();
+        fs::write(folder.join("loop.yaml"), "include: loop.yaml").unwrap();
+
+        let scene = crate::scene::load_scene(folder.join("scene.yaml").to_str().unwrap()).unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+        assert_eq!(
+            scene.world.objects[1].material.color,
+            Color::new(1.0, 0.0, 0.0)
+        );
+
+        let error = match crate::scene::load_scene(folder.join("loop.yaml").to_str().unwrap()) {
+            Err(Error::Scene(mut errors)) => errors.remove(0),
+            other => panic!("Expected scene errors, got {:?}", other),
+        };
+        assert!(error.message.contains("includes itself"));
+        assert_eq!(error.file, Some(folder.join("loop.yaml")));
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+//! Denoising of path traced images with few samples per pixel. Every pixel
+//! becomes an average of its neighbours, weighted by how alike they are: in
+//! normal and albedo, which the passes give without noise, and in the
+//! lighting around them (non-local means).
+
+use rayon::prelude::*;
+
+use crate::canvas::*;
+use crate::colors::*;
+use crate::passes::Passes;
+
+/// Joint bilateral and non-local means filter guided by the albedo and
+/// normal passes.
+#[derive(Clone, Debug)]
+pub struct Denoiser {
+    /// Pixels averaged on each side of the denoised one.
+    pub radius: u32,
+    /// Pixels on each side of the patches compared for non-local means.
+    pub patch_radius: u32,
+    /// Falloff of the weights with the distance in pixels.
+    pub spatial_sigma: f32,
+    /// Falloff with the relative difference of the lighting in patches.
+    pub color_sigma: f32,
+    pub normal_sigma: f32,
+    pub albedo_sigma: f32,
+}
+
+impl Denoiser {
+    /// Denoiser averaging `radius` pixels on each side; 5 suits most images.
+    pub fn new(radius: u32) -> Denoiser {
+        Denoiser {
+            radius,
+            patch_radius: 1,
+            spatial_sigma: 3.0,
+            color_sigma: 0.7,
+            normal_sigma: 0.2,
+            albedo_sigma: 0.1,
+        }
+    }
+
+    /// Denoised `image`, given the `albedo` and `normal` passes of the same
+    /// render.
+    pub fn denoise(self: &Self, image: &Canvas, albedo: &Canvas, normal: &Canvas) -> Canvas {
+        let (width, height) = (image.width(), image.height());
+        // The lighting, the image divided by the albedo, is filtered instead
+        // of the image so textures stay sharp. Channels without albedo, like
+        // the sky or lamps, are filtered as they are.
+        let divisor = |x: u32, y: u32| {
+            let albedo = pixel_at(albedo, x, y);
+            let channel = |value: f32| if value > 0.01 { value } else { 1.0 };
+            Color::new(channel(albedo.r), channel(albedo.g), channel(albedo.b))
+        };
+        let mut lighting = build_canvas(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let color = pixel_at(image, x, y);
+                let divisor = divisor(x, y);
+                let divided = Color::new(
+                    color.r / divisor.r,
+                    color.g / divisor.g,
+                    color.b / divisor.b,
+                );
+                write_pixel(&mut lighting, x, y, divided);
+            }
+        }
+
+        let rows: Vec<Vec<Color>> = (0..height)
+            .into_par_iter()
+            .map(|y| {
+                (0..width)
+                    .map(|x| self.filter_pixel(&lighting, albedo, normal, x, y))
+                    .collect()
+            })
+            .collect();
+        let mut denoised = build_canvas(width, height);
+        for (y, row) in rows.into_iter().enumerate() {
+            for (x, color) in row.into_iter().enumerate() {
+                let (x, y) = (x as u32, y as u32);
+                write_pixel(
+                    &mut denoised,
+                    x,
+                    y,
+                    hadamard_product(&color, &divisor(x, y)),
+                );
+            }
+        }
+        denoised
+    }
+
+    /// Denoised beauty pass.
+    pub fn denoise_passes(self: &Self, passes: &Passes) -> Canvas {
+        self.denoise(&passes.beauty, &passes.albedo, &passes.normal)
+    }
+
+    fn filter_pixel(
+        self: &Self,
+        lighting: &Canvas,
+        albedo: &Canvas,
+        normal: &Canvas,
+        x: u32,
+        y: u32,
+    ) -> Color {
+        let radius = self.radius as i64;
+        let center_albedo = pixel_at(albedo, x, y);
+        let center_normal = pixel_at(normal, x, y);
+        let mut total = Color::black();
+        let mut weights = 0.0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let (other_x, other_y) = (x as i64 + dx, y as i64 + dy);
+                if other_x < 0
+                    || other_y < 0
+                    || other_x >= lighting.width() as i64
+                    || other_y >= lighting.height() as i64
+                {
+                    continue;
+                }
+                let (other_x, other_y) = (other_x as u32, other_y as u32);
+                let spatial = (dx * dx + dy * dy) as f32 / self.spatial_sigma.powi(2);
+                let albedo_difference =
+                    difference(&center_albedo, &pixel_at(albedo, other_x, other_y))
+                        / self.albedo_sigma.powi(2);
+                let normal_difference =
+                    difference(&center_normal, &pixel_at(normal, other_x, other_y))
+                        / self.normal_sigma.powi(2);
+                let color_difference = self.patch_difference(lighting, (x, y), (other_x, other_y))
+                    / self.color_sigma.powi(2);
+                let weight = (-(spatial + albedo_difference + normal_difference) / 2.0
+                    - color_difference)
+                    .exp();
+                total = add(
+                    total,
+                    multiply(&pixel_at(lighting, other_x, other_y), weight),
+                );
+                weights += weight;
+            }
+        }
+        // The pixel itself always has a weight of one.
+        multiply(&total, 1.0 / weights)
+    }
+
+    /// Mean relative difference of the channels of the patches around
+    /// `first` and `second`, between 0 and 2 so fireflies don't stand out.
+    fn patch_difference(
+        self: &Self,
+        lighting: &Canvas,
+        first: (u32, u32),
+        second: (u32, u32),
+    ) -> f32 {
+        let radius = self.patch_radius as i64;
+        let mut total = 0.0;
+        let mut count = 0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let a = clamped_pixel(lighting, first.0 as i64 + dx, first.1 as i64 + dy);
+                let b = clamped_pixel(lighting, second.0 as i64 + dx, second.1 as i64 + dy);
+                for (a, b) in [(a.r, b.r), (a.g, b.g), (a.b, b.b)].iter() {
+                    total += (a - b).powi(2) / (0.01 + a * a + b * b);
+                    count += 1;
+                }
+            }
+        }
+        total / count as f32
+    }
+}
+
+/// Pixel at (`x`, `y`), or at the closest edge when that is outside.
+fn clamped_pixel(canvas: &Canvas, x: i64, y: i64) -> Color {
+    let x = x.clamp(0, canvas.width() as i64 - 1) as u32;
+    let y = y.clamp(0, canvas.height() as i64 - 1) as u32;
+    pixel_at(canvas, x, y)
+}
+
+fn difference(a: &Color, b: &Color) -> f32 {
+    (a.r - b.r).powi(2) + (a.g - b.g).powi(2) + (a.b - b.b).powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::denoise::*;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn filled(width: u32, height: u32, color: Color) -> Canvas {
+        let mut canvas = build_canvas(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                write_pixel(&mut canvas, x, y, color.clone());
+            }
+        }
+        canvas
+    }
+
+    fn error(canvas: &Canvas, expected: &Color) -> f32 {
+        let mut total = 0.0;
+        for y in 0..canvas.height() {
+            for x in 0..canvas.width() {
+                total += difference(&pixel_at(canvas, x, y), expected);
+            }
+        }
+        total / (canvas.width() * canvas.height()) as f32
+    }
+
+    #[test]
+    fn noise_on_a_flat_surface_is_smoothed() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut image = build_canvas(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                let value = 0.5 + 0.2 * (rng.gen::<f32>() - 0.5);
+                write_pixel(&mut image, x, y, Color::new(value, value, value));
+            }
+        }
+        let albedo = filled(16, 16, Color::new(0.8, 0.8, 0.8));
+        let normal = filled(16, 16, Color::new(0.0, 0.0, -1.0));
+        let denoised = Denoiser::new(5).denoise(&image, &albedo, &normal);
+        let gray = Color::new(0.5, 0.5, 0.5);
+        assert!(error(&denoised, &gray) < 0.2 * error(&image, &gray));
+    }
+
+    #[test]
+    fn edges_of_the_guides_stay_sharp() {
+        // Two walls facing different ways, one lit and one in the dark.
+        let mut image = build_canvas(8, 4);
+        let mut normal = build_canvas(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                let lit = x < 4;
+                let value = if lit { 1.0 } else { 0.0 };
+                write_pixel(&mut image, x, y, Color::new(value, value, value));
+                let facing = if lit { 1.0 } else { -1.0 };
+                write_pixel(&mut normal, x, y, Color::new(facing, 0.0, 0.0));
+            }
+        }
+        let albedo = filled(8, 4, Color::new(1.0, 1.0, 1.0));
+        let denoised = Denoiser::new(5).denoise(&image, &albedo, &normal);
+        assert_abs_diff_eq!(pixel_at(&denoised, 3, 2).r, 1.0, epsilon = 0.001);
+        assert_abs_diff_eq!(pixel_at(&denoised, 4, 2).r, 0.0, epsilon = 0.001);
+    }
+
+    #[test]
+    fn textures_are_kept() {
+        let light = Color::new(2.0, 2.0, 2.0);
+        let mut albedo = build_canvas(6, 6);
+        let mut image = build_canvas(6, 6);
+        for y in 0..6 {
+            for x in 0..6 {
+                let texel = if (x + y) % 2 == 0 { 0.9 } else { 0.1 };
+                let texel = Color::new(texel, texel, texel);
+                write_pixel(&mut image, x, y, hadamard_product(&texel, &light));
+                write_pixel(&mut albedo, x, y, texel);
+            }
+        }
+        let normal = filled(6, 6, Color::new(0.0, 1.0, 0.0));
+        let denoised = Denoiser::new(5).denoise(&image, &albedo, &normal);
+        for y in 0..6 {
+            for x in 0..6 {
+                assert_abs_diff_eq!(
+                    pixel_at(&denoised, x, y),
+                    pixel_at(&image, x, y),
+                    epsilon = 0.0001
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use image::ImageError;
+
+use crate::scene::SceneError;
+
+/// Anything that can go wrong between reading a scene and saving its image.
+#[derive(Debug)]
+pub enum Error {
+    /// Every problem found in a scene file.
+    Scene(Vec<SceneError>),
+    /// A file couldn't be read or written.
+    Io { filename: String, source: io::Error },
+    /// A PPM image is malformed.
+    Ppm(String),
+    /// An image couldn't be written.
+    Image {
+        filename: String,
+        source: ImageError,
+    },
+    /// An OpenEXR image couldn't be written.
+    Exr {
+        filename: String,
+        source: exr::error::Error,
+    },
+    /// A transformation flattens space, like a scale by 0, so it can't be
+    /// undone. `object` is the index in the world of the object it moves,
+    /// when it is known.
+    SingularTransformation { object: Option<usize> },
+    /// The extension of an image to save names no format that can be
+    /// written.
+    UnknownFormat { filename: String },
+}
+
+impl Error {
+    /// Exit code of the command line tool, following `sysexits.h`: 64 when
+    /// it is asked for something it can't do, 65 when the input is wrong and
+    /// 74 when a file couldn't be read or written.
+    pub fn exit_code(self: &Self) -> i32 {
+        match self {
+            Error::UnknownFormat { .. } => 64,
+            Error::Scene(_) | Error::Ppm(_) | Error::SingularTransformation { .. } => 65,
+            Error::Io { .. } | Error::Image { .. } | Error::Exr { .. } => 74,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(self: &Self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Scene(errors) => {
+                let lines: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+                write!(formatter, "{}", lines.join("\n"))
+            }
+            Error::Io { filename, source } => write!(formatter, "{}: {}", filename, source),
+            Error::Ppm(message) => write!(formatter, "invalid PPM, {}", message),
+            Error::Image { filename, source } => {
+                write!(formatter, "couldn't save {}: {}", filename, source)
+            }
+            Error::Exr { filename, source } => {
+                write!(formatter, "couldn't save {}: {}", filename, source)
+            }
+            Error::SingularTransformation {
+                object: Some(object),
+            } => write!(
+                formatter,
+                "object {} has a transformation that can't be undone",
+                object
+            ),
+            Error::SingularTransformation { object: None } => {
+                write!(formatter, "the transformation can't be undone")
+            }
+            Error::UnknownFormat { filename } => write!(
+                formatter,
+                "can't save {}: unknown format, expected png, ppm, exr or hdr",
+                filename
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(self: &Self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Image { source, .. } => Some(source),
+            Error::Exr { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+
+    #[test]
+    fn scene_errors_are_listed_one_per_line() {
+        let error = Error::Scene(vec![
+            SceneError {
+                message: "first".to_string(),
+                file: None,
+                line: Some(1),
+            },
+            SceneError {
+                message: "second".to_string(),
+                file: None,
+                line: Some(2),
+            },
+        ]);
+        assert_eq!(error.to_string(), "line 1: first\nline 2: second");
+        assert_eq!(error.exit_code(), 65);
+    }
+}
@@ -0,0 +1,200 @@
+//! High dynamic range images, which keep the radiance of the canvas as it is
+//! instead of clipping it to 8 bits: OpenEXR, with half or full floats and
+//! optionally several layers, and Radiance RGBE.
+
+use std::fs;
+
+use exr::prelude::{
+    f16, AnyChannel, AnyChannels, Encoding, FlatSamples, Image, ImageAttributes, IntegerBounds,
+    Layer, LayerAttributes, Layers, SmallVec, Vec2, WritableImage,
+};
+
+use crate::canvas::*;
+use crate::colors::Color;
+use crate::error::Error;
+
+/// Size of the floats stored in an OpenEXR image.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ExrPrecision {
+    /// 16 bits, enough for most images at half the size.
+    Half,
+    /// 32 bits, exactly the values of the canvas.
+    Float,
+}
+
+/// Saves `canvas` as an OpenEXR image with R, G and B channels.
+pub fn export_exr(canvas: &Canvas, filename: &str, precision: ExrPrecision) -> Result<(), Error> {
+    let layer = exr_layer(canvas, LayerAttributes::default(), precision);
+    write_exr(SmallVec::from_vec(vec![layer]), filename)
+}
+
+/// Saves each canvas of `layers` as a layer of an OpenEXR image with the
+/// given name, like "beauty" or "albedo". The image has the size of the
+/// first one.
+pub fn export_exr_layers(
+    layers: &[(&str, &Canvas)],
+    filename: &str,
+    precision: ExrPrecision,
+) -> Result<(), Error> {
+    let layers = layers
+        .iter()
+        .map(|(name, canvas)| exr_layer(canvas, LayerAttributes::named(*name), precision))
+        .collect();
+    write_exr(layers, filename)
+}
+
+fn exr_layer(
+    canvas: &Canvas,
+    attributes: LayerAttributes,
+    precision: ExrPrecision,
+) -> Layer<AnyChannels<FlatSamples>> {
+    let channel = |name: &str, value: fn(&Color) -> f32| {
+        let values = (0..canvas.height())
+            .flat_map(|y| (0..canvas.width()).map(move |x| value(&pixel_at(canvas, x, y))));
+        let samples = match precision {
+            ExrPrecision::Half => FlatSamples::F16(values.map(f16::from_f32).collect()),
+            ExrPrecision::Float => FlatSamples::F32(values.collect()),
+        };
+        AnyChannel::new(name, samples)
+    };
+    let channels = AnyChannels::sort(SmallVec::from_vec(vec![
+        channel("R", |color| color.r),
+        channel("G", |color| color.g),
+        channel("B", |color| color.b),
+    ]));
+    let size = (canvas.width() as usize, canvas.height() as usize);
+    Layer::new(size, attributes, Encoding::SMALL_LOSSLESS, channels)
+}
+
+fn write_exr(layers: Layers<AnyChannels<FlatSamples>>, filename: &str) -> Result<(), Error> {
+    let size = layers.first().map_or(Vec2(0, 0), |layer| layer.size);
+    let attributes = ImageAttributes::new(IntegerBounds::from_dimensions(size));
+    Image::from_layers(attributes, layers)
+        .write()
+        .to_file(filename)
+        .map_err(|source| Error::Exr {
+            filename: filename.to_string(),
+            source,
+        })
+}
+
+/// Saves `canvas` as a Radiance image, with uncompressed scanlines. Negative
+/// channels are stored as 0.
+pub fn export_rgbe(canvas: &Canvas, filename: &str) -> Result<(), Error> {
+    fs::write(filename, canvas_to_rgbe(canvas)).map_err(|source| Error::Io {
+        filename: filename.to_string(),
+        source,
+    })
+}
+
+pub fn canvas_to_rgbe(canvas: &Canvas) -> Vec<u8> {
+    let mut rgbe = format!(
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        canvas.height(),
+        canvas.width()
+    )
+    .into_bytes();
+    for y in 0..canvas.height() {
+        for x in 0..canvas.width() {
+            rgbe.extend(&to_rgbe(&pixel_at(canvas, x, y)));
+        }
+    }
+    rgbe
+}
+
+/// Shares the exponent of the brightest channel among the three, so each
+/// keeps 8 bits of mantissa relative to it.
+fn to_rgbe(color: &Color) -> [u8; 4] {
+    let (r, g, b) = (color.r.max(0.0), color.g.max(0.0), color.b.max(0.0));
+    let brightest = r.max(g).max(b);
+    if brightest < 1e-32 || !brightest.is_finite() {
+        return [0, 0, 0, 0];
+    }
+    // brightest = mantissa * 2^exponent, with the mantissa in [0.5, 1).
+    let exponent = brightest.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2.0_f32.powi(exponent);
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hdr::*;
+
+    use exr::prelude::{read_all_flat_layers_from_file, Text};
+    use std::env;
+
+    fn from_rgbe(rgbe: &[u8]) -> Color {
+        let scale = 2.0_f32.powi(rgbe[3] as i32 - 136);
+        let channel = |value: u8| (value as f32 + 0.5) * scale;
+        Color::new(channel(rgbe[0]), channel(rgbe[1]), channel(rgbe[2]))
+    }
+
+    fn bright_canvas() -> Canvas {
+        let mut canvas = build_canvas(3, 2);
+        write_pixel(&mut canvas, 0, 0, Color::new(12.5, 1.0, 0.25));
+        write_pixel(&mut canvas, 2, 1, Color::new(0.0, 0.003, 1000.0));
+        canvas
+    }
+
+    #[test]
+    fn rgbe_keeps_values_above_one() {
+        let color = Color::new(12.5, 1.0, 0.25);
+        assert_abs_diff_eq!(from_rgbe(&to_rgbe(&color)), color, epsilon = 0.06);
+        let color = Color::new(0.001, 0.002, 0.0005);
+        assert_abs_diff_eq!(from_rgbe(&to_rgbe(&color)), color, epsilon = 0.00001);
+        assert_eq!(to_rgbe(&Color::black()), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(&Color::new(-1.0, 0.0, 0.0)), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rgbe_images_have_a_header_and_four_bytes_per_pixel() {
+        let rgbe = canvas_to_rgbe(&bright_canvas());
+        let header = "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 3\n";
+        assert!(rgbe.starts_with(header.as_bytes()));
+        assert_eq!(rgbe.len(), header.len() + 3 * 2 * 4);
+        let pixel = &rgbe[header.len()..header.len() + 4];
+        assert_abs_diff_eq!(
+            from_rgbe(pixel),
+            Color::new(12.5, 1.0, 0.25),
+            epsilon = 0.06
+        );
+    }
+
+    #[test]
+    fn exr_images_keep_the_radiance() {
+        let folder = env::temp_dir().join(format!("alart-hdr-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let canvas = bright_canvas();
+        let albedo = build_canvas(3, 2);
+        for precision in [ExrPrecision::Half, ExrPrecision::Float].iter() {
+            let filename = folder.join("image.exr");
+            let filename = filename.to_str().unwrap();
+            export_exr_layers(
+                &[("beauty", &canvas), ("albedo", &albedo)],
+                filename,
+                *precision,
+            )
+            .unwrap();
+            let image = read_all_flat_layers_from_file(filename).unwrap();
+            assert_eq!(image.layer_data.len(), 2);
+            let beauty = &image.layer_data[0];
+            assert_eq!(beauty.attributes.layer_name, Some(Text::from("beauty")));
+            // Channels are sorted by name: B, G, R.
+            let red = &beauty.channel_data.list[2];
+            assert_eq!(red.name, Text::from("R"));
+            assert_abs_diff_eq!(red.sample_data.value_by_flat_index(0).to_f32(), 12.5);
+            let blue = &beauty.channel_data.list[0];
+            assert_abs_diff_eq!(blue.sample_data.value_by_flat_index(5).to_f32(), 1000.0);
+
+            export_exr(&canvas, filename, *precision).unwrap();
+            let image = read_all_flat_layers_from_file(filename).unwrap();
+            assert_eq!(image.layer_data.len(), 1);
+        }
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -4,7 +4,11 @@ use crate::spheres::Sphere;
 
 pub struct Intersection {
     pub t: f32,
-    pub object: Sphere
+    pub object: Sphere,
+    /// Index of the object in the world, when found by `World::intersect`.
+    /// Moving objects are hit as copies frozen in time, so this is what tells
+    /// which object of the world was hit.
+    pub object_index: Option<usize>,
 }
 
 pub fn hit(intersections: Vec<Intersection>) -> Option<Intersection> {
@@ -21,11 +25,13 @@ mod tests {
         let intersections = vec![
             Intersection{
                 t: 1.0,
-                object: Sphere::new()
+                object: Sphere::new(),
+                object_index: None
             },
             Intersection{
                 t: 3.0,
-                object: Sphere::new()
+                object: Sphere::new(),
+                object_index: None
             }
         ];
         assert_eq!(
@@ -38,11 +44,13 @@ mod tests {
         let intersections = vec![
             Intersection{
                 t: 1.0,
-                object: Sphere::new()
+                object: Sphere::new(),
+                object_index: None
             },
             Intersection{
                 t: -1.0,
-                object: Sphere::new()
+                object: Sphere::new(),
+                object_index: None
             }
         ];
         assert_eq!(
@@ -55,11 +63,13 @@ mod tests {
         let intersections = vec![
             Intersection{
                 t: -4.0,
-                object: Sphere::new()
+                object: Sphere::new(),
+                object_index: None
             },
             Intersection{
                 t: -1.0,
-                object: Sphere::new()
+                object: Sphere::new(),
+                object_index: None
             }
         ];
         assert!(hit(intersections).is_none());
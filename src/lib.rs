@@ -0,0 +1,81 @@
+//! At last a ray tracer!
+//!
+//! Builds worlds out of spheres, materials and lights, and renders them
+//! through a camera with the path tracer or with direct lighting only:
+//!
+//! ```no_run
+//! use alart::{export_png, point, Camera, Color, ColorSpace, Light, PathTracer, Sphere, World};
+//!
+//! let mut world = World::new();
+//! world.objects.push(Sphere::new());
+//! world.lights.push(Light::point(point(-10.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0)));
+//! let mut camera = Camera::new(320, 240, std::f32::consts::PI / 3.0);
+//! camera.transformation = alart::transformations::translation(0.0, 0.0, -5.0);
+//! let canvas = PathTracer::new(16).render(&camera, &world)?;
+//! export_png(&canvas, "sphere.png", ColorSpace::Srgb)?;
+//! # Ok::<(), alart::Error>(())
+//! ```
+//!
+//! Scenes can also be read from YAML files with `load_scene`.
+
+#[cfg_attr(test, macro_use)]
+extern crate approx;
+extern crate log;
+
+extern crate crc32fast;
+extern crate exr;
+extern crate image;
+extern crate ndarray;
+extern crate ndarray_linalg;
+extern crate openblas_src;
+extern crate rand;
+extern crate rayon;
+extern crate yaml_rust;
+
+pub mod bsdfs;
+pub mod camera;
+pub mod canvas;
+pub mod color_spaces;
+pub mod colors;
+pub mod denoise;
+mod definitions;
+pub mod error;
+pub mod hdr;
+pub mod intersections;
+pub mod lights;
+pub mod materials;
+pub mod media;
+pub mod passes;
+pub mod path_tracer;
+pub mod ppm;
+pub mod rays;
+pub mod sampling;
+pub mod scene;
+pub mod spheres;
+pub mod stereo;
+pub mod tone_mapping;
+pub mod transformations;
+pub mod tuples;
+mod validation;
+pub mod world;
+mod yaml;
+
+pub use crate::camera::{Camera, Projection};
+pub use crate::canvas::{export_png, Canvas};
+pub use crate::color_spaces::ColorSpace;
+pub use crate::colors::Color;
+pub use crate::denoise::Denoiser;
+pub use crate::error::Error;
+pub use crate::hdr::{export_exr, export_exr_layers, export_rgbe, ExrPrecision};
+pub use crate::lights::Light;
+pub use crate::materials::Material;
+pub use crate::passes::Passes;
+pub use crate::path_tracer::PathTracer;
+pub use crate::ppm::{export_ppm, import_ppm, PpmFormat};
+pub use crate::rays::Ray;
+pub use crate::scene::{load_scene, parse_scene, Scene};
+pub use crate::spheres::Sphere;
+pub use crate::stereo::{StereoLayout, StereoRig};
+pub use crate::tone_mapping::{ToneMapper, ToneMapping};
+pub use crate::tuples::{point, vector, Tuple};
+pub use crate::world::World;
@@ -1,14 +1,295 @@
-use crate::colors::Color;
-use crate::tuples::Tuple;
+use crate::colors::{multiply, Color};
+use crate::tuples::*;
 
+/// How the intensity of a positioned light decreases with the distance `d`
+/// to the lit point. `Polynomial` divides the intensity by
+/// `constant + linear * d + quadratic * d * d`.
 #[derive(PartialEq, Clone, Debug)]
-pub struct Light {
+pub enum Attenuation {
+    None,
+    InverseSquare,
+    Polynomial {
+        constant: f32,
+        linear: f32,
+        quadratic: f32,
+    },
+}
+
+impl Attenuation {
+    pub fn factor(self: &Self, distance: f32) -> f32 {
+        let divisor = match self {
+            Attenuation::None => 1.0,
+            Attenuation::InverseSquare => distance * distance,
+            Attenuation::Polynomial {
+                constant,
+                linear,
+                quadratic,
+            } => constant + linear * distance + quadratic * distance * distance,
+        };
+        if divisor > 0.0 {
+            1.0 / divisor
+        } else {
+            1.0
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct PointLight {
+    pub position: Tuple,
+    pub intensity: Color,
+    pub attenuation: Attenuation,
+}
+
+/// A light infinitely far away, like the sun. All its rays are parallel and
+/// it does not lose intensity with distance.
+#[derive(PartialEq, Clone, Debug)]
+pub struct DirectionalLight {
+    pub direction: Tuple,
+    pub intensity: Color,
+}
+
+/// A point light restricted to a cone. Points inside the inner angle get the
+/// full intensity and it fades smoothly to black at the outer angle. Angles
+/// are in radians, measured from the direction of the spot.
+#[derive(PartialEq, Clone, Debug)]
+pub struct SpotLight {
     pub position: Tuple,
-    pub intensity: Color
+    pub direction: Tuple,
+    pub intensity: Color,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    pub attenuation: Attenuation,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Light {
+    Point(PointLight),
+    Directional(DirectionalLight),
+    Spot(SpotLight),
+}
+
+impl Light {
+    pub fn point(position: Tuple, intensity: Color) -> Light {
+        Light::Point(PointLight {
+            position,
+            intensity,
+            attenuation: Attenuation::None,
+        })
+    }
+
+    pub fn directional(direction: Tuple, intensity: Color) -> Light {
+        Light::Directional(DirectionalLight {
+            direction: direction.normalize(),
+            intensity,
+        })
+    }
+
+    pub fn spot(
+        position: Tuple,
+        direction: Tuple,
+        intensity: Color,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Light {
+        Light::Spot(SpotLight {
+            position,
+            direction: direction.normalize(),
+            intensity,
+            inner_angle,
+            outer_angle,
+            attenuation: Attenuation::None,
+        })
+    }
+
+    /// Returns the same light with the given attenuation. Directional lights
+    /// are infinitely far away and are returned unchanged.
+    pub fn with_attenuation(self: Self, attenuation: Attenuation) -> Light {
+        match self {
+            Light::Point(light) => Light::Point(PointLight {
+                attenuation,
+                ..light
+            }),
+            Light::Directional(light) => Light::Directional(light),
+            Light::Spot(light) => Light::Spot(SpotLight {
+                attenuation,
+                ..light
+            }),
+        }
+    }
+
+    pub fn intensity(self: &Self) -> Color {
+        match self {
+            Light::Point(light) => light.intensity.clone(),
+            Light::Directional(light) => light.intensity.clone(),
+            Light::Spot(light) => light.intensity.clone(),
+        }
+    }
+
+    /// Normalized vector going from `point_at` towards the light.
+    pub fn direction_from(self: &Self, point_at: &Tuple) -> Tuple {
+        match self {
+            Light::Point(light) => sub(&light.position, point_at).normalize(),
+            Light::Directional(light) => light.direction.negate(),
+            Light::Spot(light) => sub(&light.position, point_at).normalize(),
+        }
+    }
+
+    pub fn distance_from(self: &Self, point_at: &Tuple) -> f32 {
+        match self {
+            Light::Point(light) => magnitude(&sub(&light.position, point_at)),
+            Light::Directional(_) => f32::INFINITY,
+            Light::Spot(light) => magnitude(&sub(&light.position, point_at)),
+        }
+    }
+
+    /// Intensity of the light that reaches `point_at`.
+    pub fn intensity_at(self: &Self, point_at: &Tuple) -> Color {
+        match self {
+            Light::Point(light) => multiply(
+                &light.intensity,
+                light.attenuation.factor(self.distance_from(point_at)),
+            ),
+            Light::Directional(light) => light.intensity.clone(),
+            Light::Spot(light) => {
+                let direction_to_point = sub(point_at, &light.position).normalize();
+                let cos_angle = dot(&direction_to_point, &light.direction);
+                let falloff =
+                    smoothstep(light.outer_angle.cos(), light.inner_angle.cos(), cos_angle);
+                let attenuation = light.attenuation.factor(self.distance_from(point_at));
+                multiply(&light.intensity, falloff * attenuation)
+            }
+        }
+    }
 }
 
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge0 >= edge1 {
+        return if x >= edge1 { 1.0 } else { 0.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
 
 #[cfg(test)]
 mod tests {
+    use crate::lights::*;
+
+    use std::f32::consts::PI;
+
+    #[test]
+    fn a_point_light_shines_from_its_position() {
+        let light = Light::point(point(0.0, 10.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        assert_abs_diff_eq!(
+            light.direction_from(&point(0.0, 0.0, 0.0)),
+            vector(0.0, 1.0, 0.0)
+        );
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn a_directional_light_has_parallel_rays() {
+        let light = Light::directional(vector(0.0, -2.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        assert_abs_diff_eq!(
+            light.direction_from(&point(0.0, 0.0, 0.0)),
+            vector(0.0, 1.0, 0.0)
+        );
+        assert_abs_diff_eq!(
+            light.direction_from(&point(100.0, -50.0, 3.0)),
+            vector(0.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn a_directional_light_has_no_falloff() {
+        let light = Light::directional(vector(0.0, -1.0, 0.0), Color::new(0.5, 0.5, 0.5));
+        assert_abs_diff_eq!(
+            light.intensity_at(&point(1000.0, -1000.0, 1000.0)),
+            Color::new(0.5, 0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn a_spot_light_is_full_intensity_inside_the_inner_cone() {
+        let light = Light::spot(
+            point(0.0, 10.0, 0.0),
+            vector(0.0, -1.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+            PI / 8.0,
+            PI / 4.0,
+        );
+        assert_abs_diff_eq!(
+            light.intensity_at(&point(0.0, 0.0, 0.0)),
+            Color::new(1.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn a_spot_light_is_black_outside_the_outer_cone() {
+        let light = Light::spot(
+            point(0.0, 10.0, 0.0),
+            vector(0.0, -1.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+            PI / 8.0,
+            PI / 4.0,
+        );
+        assert_abs_diff_eq!(light.intensity_at(&point(20.0, 0.0, 0.0)), Color::black());
+    }
+
+    #[test]
+    fn a_spot_light_fades_between_the_cones() {
+        let light = Light::spot(
+            point(0.0, 10.0, 0.0),
+            vector(0.0, -1.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+            PI / 8.0,
+            PI / 4.0,
+        );
+        let angle = 3.0 * PI / 16.0;
+        let intensity = light.intensity_at(&point(10.0 * angle.tan(), 0.0, 0.0));
+        assert!(intensity.r > 0.0 && intensity.r < 1.0);
+    }
+
+    #[test]
+    fn a_light_without_attenuation_keeps_its_intensity() {
+        let light = Light::point(point(0.0, 10.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        assert_abs_diff_eq!(
+            light.intensity_at(&point(0.0, -90.0, 0.0)),
+            Color::new(1.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn inverse_square_attenuation() {
+        let light = Light::point(point(0.0, 2.0, 0.0), Color::new(1.0, 1.0, 1.0))
+            .with_attenuation(Attenuation::InverseSquare);
+        assert_abs_diff_eq!(
+            light.intensity_at(&point(0.0, 0.0, 0.0)),
+            Color::new(0.25, 0.25, 0.25)
+        );
+    }
+
+    #[test]
+    fn polynomial_attenuation() {
+        let light = Light::point(point(0.0, 2.0, 0.0), Color::new(1.0, 1.0, 1.0)).with_attenuation(
+            Attenuation::Polynomial {
+                constant: 1.0,
+                linear: 0.5,
+                quadratic: 0.25,
+            },
+        );
+        assert_abs_diff_eq!(
+            light.intensity_at(&point(0.0, 0.0, 0.0)),
+            Color::new(1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0)
+        );
+    }
+
+    #[test]
+    fn directional_lights_ignore_attenuation() {
+        let light = Light::directional(vector(0.0, -1.0, 0.0), Color::new(1.0, 1.0, 1.0))
+            .with_attenuation(Attenuation::InverseSquare);
+        assert_abs_diff_eq!(
+            light.intensity_at(&point(0.0, -1000.0, 0.0)),
+            Color::new(1.0, 1.0, 1.0)
+        );
+    }
+}
@@ -1,75 +1,278 @@
-#[macro_use]
-extern crate approx;
+extern crate alart;
 extern crate log;
 extern crate pretty_env_logger;
+extern crate rayon;
+extern crate structopt;
 
-extern crate ndarray;
-extern crate ndarray_linalg;
-extern crate openblas_src;
-
-mod canvas;
-mod colors;
-mod intersections;
-mod lights;
-mod materials;
-mod rays;
-mod spheres;
-mod transformations;
-mod tuples;
-
-use crate::colors::Color;
-use crate::intersections::hit;
-use crate::lights::Light;
-use crate::materials::*;
-use crate::rays::Ray;
-use crate::spheres::Sphere;
-use crate::tuples::*;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+use structopt::StructOpt;
+
+use alart::{
+    camera, export_exr, export_exr_layers, export_png, export_ppm, export_rgbe, load_scene, Canvas,
+    Color, ColorSpace, Denoiser, Error, ExrPrecision, PathTracer, PpmFormat, Scene, ToneMapper,
+    ToneMapping,
+};
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "alart", about = "At last a ray tracer!")]
+enum Command {
+    /// Renders a scene file with the path tracer into an image, whose format
+    /// follows the extension of the output: png, ppm, exr or hdr.
+    Render {
+        scene: String,
+        #[structopt(short, long, default_value = "render.png")]
+        output: String,
+        /// Width of the image; keeps the aspect ratio when alone.
+        #[structopt(long, parse(try_from_str = image_size))]
+        width: Option<u32>,
+        /// Height of the image; keeps the aspect ratio when alone.
+        #[structopt(long, parse(try_from_str = image_size))]
+        height: Option<u32>,
+        /// Samples per pixel.
+        #[structopt(long, default_value = "16")]
+        spp: u32,
+        /// Threads to render with, all the cores by default.
+        #[structopt(long)]
+        threads: Option<usize>,
+        /// Renders quickly with direct lighting only, ignoring --spp.
+        #[structopt(long)]
+        preview: bool,
+        /// Stops of exposure compensation for png and ppm images.
+        #[structopt(long, default_value = "0", allow_hyphen_values = true)]
+        exposure: f32,
+        /// Curve bringing highlights into png and ppm images: clamp, reinhard,
+        /// extended-reinhard[:white], aces or hable.
+        #[structopt(long, default_value = "clamp")]
+        tone_map: ToneMapper,
+        /// Color space of png and ppm images: srgb, rec709, display-p3 or
+        /// linear.
+        #[structopt(long, default_value = "srgb")]
+        color_space: ColorSpace,
+        /// OpenEXR image to save the passes of the render in, as layers:
+        /// depth, normal, albedo, IDs, lighting components and shadows.
+        #[structopt(long, conflicts_with = "preview")]
+        passes: Option<String>,
+        /// Denoises the image with the guide of its albedo and normals, for
+        /// renders with few samples per pixel.
+        #[structopt(long, conflicts_with = "preview")]
+        denoise: bool,
+    },
+    /// Checks a scene file and lists every problem in it.
+    Validate { scene: String },
+    /// Prints what a scene file holds.
+    Info { scene: String },
+}
 
 fn main() {
     pretty_env_logger::init();
 
-    log::info!("Staring alart");
-    let mut canvas = canvas::build_canvas(1000, 1000);
-
-    let mut sphere = Sphere::new();
-    sphere.transformation = transformations::translation(0.0, 0.0, 3.0);
-    sphere.material = Material::new();
-    sphere.material.color = Color::new(1.0, 0.2, 1.0);
-
-    let light = Light {
-        position: point(-10.0, 10.0, -10.0),
-        intensity: Color::new(1.0, 1.0, 1.0),
-    };
-
-    for i in 0..1000 {
-        for j in 0..1000 {
-            let ray = Ray {
-                origin: point(0.0, 0.0, 0.0),
-                direction: vector(-0.5 + (i as f32) * 0.001, 0.5 - (j as f32) * 0.001, 1.0)
-                    .normalize(),
+    if let Err(error) = run(Command::from_args()) {
+        eprintln!("{}", error);
+        process::exit(error.exit_code());
+    }
+}
+
+fn run(command: Command) -> Result<(), Error> {
+    match command {
+        Command::Render {
+            scene,
+            output,
+            width,
+            height,
+            spp,
+            threads,
+            preview,
+            exposure,
+            tone_map,
+            color_space,
+            passes,
+            denoise,
+        } => {
+            let format = output_format(&output)?;
+            let Scene { mut camera, world } = load_scene(&scene)?;
+            match (width, height) {
+                (Some(width), Some(height)) => {
+                    camera.width = width;
+                    camera.height = height;
+                }
+                (Some(width), None) => {
+                    camera.height = keep_aspect(width, camera.height, camera.width);
+                    camera.width = width;
+                }
+                (None, Some(height)) => {
+                    camera.width = keep_aspect(height, camera.width, camera.height);
+                    camera.height = height;
+                }
+                (None, None) => {}
+            }
+            if let Some(threads) = threads {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build_global()
+                    .expect("The thread pool is only built once");
+            }
+            log::info!("Rendering {} at {}x{}", scene, camera.width, camera.height);
+            let start = Instant::now();
+            let tracer = PathTracer::new(spp);
+            let canvas = if preview {
+                camera::render(&camera, &world)?
+            } else if passes.is_some() || denoise {
+                let rendered = tracer.render_passes(&camera, &world)?;
+                if let Some(filename) = &passes {
+                    export_exr_layers(&rendered.layers(), filename, ExrPrecision::Float)?;
+                }
+                if denoise {
+                    Denoiser::new(5).denoise_passes(&rendered)
+                } else {
+                    rendered.beauty
+                }
+            } else {
+                tracer.render(&camera, &world)?
+            };
+            log::info!("Rendered in {:.1?}", start.elapsed());
+            let tone_mapping = ToneMapping {
+                exposure,
+                mapper: tone_map,
             };
+            save(&canvas, &output, format, &tone_mapping, color_space)?;
+        }
+        Command::Validate { scene } => {
+            load_scene(&scene)?;
+            println!("{} is valid", scene);
+        }
+        Command::Info { scene } => {
+            let Scene { camera, world } = load_scene(&scene)?;
+            let emissive = world
+                .objects
+                .iter()
+                .filter(|object| object.material.emission != Color::black())
+                .count();
+            println!(
+                "camera: {}x{}, {:?}",
+                camera.width, camera.height, camera.projection
+            );
+            println!("objects: {} spheres", world.objects.len());
+            println!(
+                "lights: {}, and {} emissive objects",
+                world.lights.len(),
+                emissive
+            );
+            match world.bounds() {
+                Some((low, high)) => println!(
+                    "bounds: ({}, {}, {}) to ({}, {}, {})",
+                    low.x(),
+                    low.y(),
+                    low.z(),
+                    high.x(),
+                    high.y(),
+                    high.z()
+                ),
+                None => println!("bounds: empty"),
+            }
+        }
+    }
+    Ok(())
+}
 
-            let maybe_hit_with_sphere = hit(sphere.intersect(&ray));
+/// Size of the image given on the command line, which can't be 0.
+fn image_size(size: &str) -> Result<u32, String> {
+    match size.parse::<u32>() {
+        Ok(0) => Err("the image can't be 0 pixels wide or high".to_string()),
+        Ok(size) => Ok(size),
+        Err(error) => Err(error.to_string()),
+    }
+}
 
-            if maybe_hit_with_sphere.is_some() {
-                let hit_with_sphere = maybe_hit_with_sphere.unwrap();
-                let point = ray.position(hit_with_sphere.t);
-                let normal_of_hit = hit_with_sphere.object.normal_at(&point);
+/// Side of the image keeping the aspect ratio `side / other_side` of the
+/// camera once the other side is `size`, in 64 bits so it can't overflow.
+fn keep_aspect(size: u32, side: u32, other_side: u32) -> u32 {
+    let scaled = u64::from(size) * u64::from(side) / u64::from(other_side);
+    scaled.max(1).min(u64::from(u32::MAX)) as u32
+}
 
-                let eye = ray.direction.negate();
+/// Formats of the images the renders are saved in.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Format {
+    Png,
+    Ppm,
+    Exr,
+    Hdr,
+}
 
-                let color = lighting(
-                    hit_with_sphere.object.material,
-                    light.clone(),
-                    &point,
-                    &eye,
-                    &normal_of_hit,
-                );
+/// Format given by the extension of `filename`, PNG when it has none.
+fn output_format(filename: &str) -> Result<Format, Error> {
+    let extension = Path::new(filename)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        None | Some("png") => Ok(Format::Png),
+        Some("ppm") => Ok(Format::Ppm),
+        Some("exr") => Ok(Format::Exr),
+        Some("hdr") => Ok(Format::Hdr),
+        Some(_) => Err(Error::UnknownFormat {
+            filename: filename.to_string(),
+        }),
+    }
+}
 
-                canvas::write_pixel(&mut canvas, i, j, color);
-            }
-        }
+/// Saves `canvas` in `format`. Only 8 bit images are tone mapped and encoded
+/// in `color_space`, the others keep the radiance.
+fn save(
+    canvas: &Canvas,
+    filename: &str,
+    format: Format,
+    tone_mapping: &ToneMapping,
+    color_space: ColorSpace,
+) -> Result<(), Error> {
+    match format {
+        Format::Ppm => export_ppm(
+            &tone_mapping.apply(canvas),
+            filename,
+            PpmFormat::P6,
+            color_space,
+        ),
+        Format::Exr => export_exr(canvas, filename, ExrPrecision::Half),
+        Format::Hdr => export_rgbe(canvas, filename),
+        Format::Png => export_png(&tone_mapping.apply(canvas), filename, color_space),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn the_extension_picks_the_format() {
+        assert_eq!(output_format("render.png").unwrap(), Format::Png);
+        assert_eq!(output_format("render").unwrap(), Format::Png);
+        assert_eq!(output_format("out/Render.EXR").unwrap(), Format::Exr);
+        assert_eq!(output_format("render.hdr").unwrap(), Format::Hdr);
+        let error = output_format("render.jpg").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "can't save render.jpg: unknown format, expected png, ppm, exr or hdr"
+        );
+        assert_eq!(error.exit_code(), 64);
     }
 
-    canvas::export_png(&canvas, "sample.png");
+    #[test]
+    fn image_sizes_keep_the_aspect_ratio() {
+        assert_eq!(keep_aspect(800, 300, 400), 600);
+        assert_eq!(keep_aspect(3_000_000_000, 300, 400), 2_250_000_000);
+        assert_eq!(keep_aspect(u32::MAX, 2, 1), u32::MAX);
+        assert_eq!(keep_aspect(1, 1, 400), 1);
+    }
+
+    #[test]
+    fn zero_sized_images_are_refused() {
+        assert_eq!(image_size("640"), Ok(640));
+        assert!(image_size("0").is_err());
+        assert!(image_size("-1").is_err());
+        assert!(
+            Command::from_iter_safe(&["alart", "render", "scene.yaml", "--width", "0"]).is_err()
+        );
+    }
 }
@@ -1,14 +1,53 @@
+use crate::bsdfs::*;
 use crate::colors::*;
 use crate::lights::Light;
+use crate::media::Medium;
 use crate::tuples::*;
 
+use std::f32::consts::PI;
+
+/// Reflection model used to turn the material parameters into a BSDF.
+#[derive(PartialEq, Clone, Debug)]
+pub enum BsdfModel {
+    Lambertian,
+    Phong,
+    BlinnPhong,
+    /// `roughness` is the standard deviation of the facet slopes in radians.
+    OrenNayar { roughness: f32 },
+    /// glTF style physically based material. `color` is the base color.
+    Microfacet { metallic: f32, roughness: f32 },
+    /// Glass with the given index of refraction, frosted when rough. `color`
+    /// tints the transmitted light.
+    RoughDielectric { ior: f32, roughness: f32 },
+    /// Varnish over another model, optionally with a thin film on top.
+    ClearCoat {
+        base: Box<BsdfModel>,
+        strength: f32,
+        ior: f32,
+        roughness: f32,
+        thin_film: Option<ThinFilm>,
+    },
+    /// Invisible surface that only bounds the medium of the material.
+    Interface,
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub struct Material {
     pub color: Color,
-    ambient: f32,
-    diffuse: f32,
-    specular: f32,
-    shininess: f32,
+    pub emission: Color,
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    pub shininess: f32,
+    pub model: BsdfModel,
+    /// What fills the inside of the object, if anything.
+    pub medium: Option<Medium>,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material::new()
+    }
 }
 
 impl Material {
@@ -19,10 +58,84 @@ impl Material {
                 g: 1.0,
                 b: 1.0,
             },
+            emission: Color::black(),
             ambient: 0.1,
             diffuse: 0.9,
             specular: 0.9,
             shininess: 200.0,
+            model: BsdfModel::Phong,
+            medium: None,
+        }
+    }
+
+    pub fn bsdf(self: &Self) -> Box<dyn Bsdf> {
+        self.bsdf_for_side(true)
+    }
+
+    /// BSDF seen from outside the object when `front_face` is true and from
+    /// inside otherwise. Only transmissive models tell them apart.
+    pub fn bsdf_for_side(self: &Self, front_face: bool) -> Box<dyn Bsdf> {
+        self.model_bsdf(&self.model, front_face)
+    }
+
+    fn model_bsdf(self: &Self, model: &BsdfModel, front_face: bool) -> Box<dyn Bsdf> {
+        let diffuse = multiply(&self.color, self.diffuse);
+        // The weights of the classic renderer may add up above 1, like those
+        // of the default material, and bounced light would then grow at
+        // every bounce. Both lobes are scaled down to reflect at most all of
+        // it.
+        let total = diffuse.r.max(diffuse.g).max(diffuse.b) + self.specular;
+        let conserving = if total > 1.0 { 1.0 / total } else { 1.0 };
+        let glossy_diffuse = multiply(&diffuse, conserving);
+        let specular = self.specular * conserving;
+        let specular = Color::new(specular, specular, specular);
+        match model {
+            BsdfModel::Lambertian => Box::new(Lambertian { albedo: diffuse }),
+            BsdfModel::Phong => Box::new(Phong {
+                diffuse: glossy_diffuse,
+                specular,
+                shininess: self.shininess,
+            }),
+            BsdfModel::BlinnPhong => Box::new(BlinnPhong {
+                diffuse: glossy_diffuse,
+                specular,
+                shininess: self.shininess,
+            }),
+            BsdfModel::OrenNayar { roughness } => Box::new(OrenNayar {
+                albedo: diffuse,
+                roughness: *roughness,
+            }),
+            BsdfModel::Microfacet {
+                metallic,
+                roughness,
+            } => Box::new(Microfacet {
+                base_color: self.color.clone(),
+                metallic: *metallic,
+                roughness: *roughness,
+            }),
+            BsdfModel::RoughDielectric { ior, roughness } => Box::new(RoughDielectric {
+                tint: self.color.clone(),
+                eta: if front_face { *ior } else { 1.0 / *ior },
+                roughness: *roughness,
+            }),
+            BsdfModel::ClearCoat {
+                base,
+                strength,
+                ior,
+                roughness,
+                thin_film,
+            } => Box::new(ClearCoat {
+                base: self.model_bsdf(base, front_face),
+                strength: *strength,
+                ior: *ior,
+                roughness: *roughness,
+                thin_film: thin_film.clone(),
+            }),
+            // Renderers go straight through interfaces, there is nothing to
+            // scatter.
+            BsdfModel::Interface => Box::new(Lambertian {
+                albedo: Color::black(),
+            }),
         }
     }
 }
@@ -34,20 +147,38 @@ pub fn lighting(
     eye_vector: &Tuple,
     normal_vector: &Tuple,
 ) -> Color {
-    let effective_color = hadamard_product(&material.color, &light.intensity);
+    let effective_color = hadamard_product(&material.color, &light.intensity());
     let ambient_contribution = multiply(&effective_color, material.ambient);
 
-    let direction_to_light_source = &crate::tuples::sub(&light.position, point_at).normalize();
+    let light_intensity = light.intensity_at(point_at);
+    let direction_to_light_source = &light.direction_from(point_at);
 
     let light_dot_normal = dot(&direction_to_light_source, normal_vector);
     let diffuse_contribution: Color;
     let specular_contribution: Color;
 
+    if material.model != BsdfModel::Phong {
+        // The classic Phong formula below is kept for the Phong model so
+        // scenes look as they always did. Other models are evaluated through
+        // their BSDF. Lights follow the classic convention, where a white
+        // diffuse surface facing a light is as bright as it, while a white
+        // Lambertian BSDF is 1 / PI: the radiance is scaled by PI to match.
+        let reflected = if light_dot_normal > 0.0 {
+            let bsdf_value =
+                material.bsdf().evaluate(normal_vector, eye_vector, direction_to_light_source);
+            multiply(&hadamard_product(&bsdf_value, &light_intensity), PI * light_dot_normal)
+        } else {
+            Color::black()
+        };
+        return crate::colors::add(ambient_contribution, reflected);
+    }
+
     if light_dot_normal < 0.0 {
         diffuse_contribution = Color::black();
         specular_contribution = Color::black();
     } else {
-        diffuse_contribution = multiply(&multiply(&effective_color, material.diffuse), light_dot_normal);
+        let lit_color = hadamard_product(&material.color, &light_intensity);
+        diffuse_contribution = multiply(&multiply(&lit_color, material.diffuse), light_dot_normal);
 
         let reflect_vector = reflect(&direction_to_light_source.negate(), normal_vector);
         let reflect_dot_eye = dot(&reflect_vector, eye_vector);
@@ -56,7 +187,7 @@ pub fn lighting(
             specular_contribution = Color::black();
         } else {
             let factor = reflect_dot_eye.powf(material.shininess);
-            specular_contribution = multiply(&multiply(&light.intensity, material.specular), factor);
+            specular_contribution = multiply(&multiply(&light_intensity, material.specular), factor);
         }
     }
 
@@ -67,20 +198,16 @@ pub fn lighting(
 mod tests {
     use crate::materials::*;
 
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
     #[test]
     fn lighting_with_the_eye_between_the_light_and_the_surface() {
         let material = Material::new();
         let position = point(0.0, 0.0, 0.0);
         let eye_vector = vector(0.0, 1.0, -1.0);
         let normal_vector = vector(0.0, 0.0, -1.0);
-        let light = Light {
-            position: point(0.0, 0.0, -10.0),
-            intensity: Color {
-                r: 1.0,
-                g: 1.0,
-                b: 1.0,
-            },
-        };
+        let light = Light::point(point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
         let calculated_color = lighting(material, light, &position, &eye_vector, &normal_vector);
         assert_abs_diff_eq!(
             calculated_color,
@@ -98,14 +225,7 @@ mod tests {
         let position = point(0.0, 0.0, 0.0);
         let eye_vector = vector(0.0, 2.0_f32.sqrt() / 2.0, -2.0_f32.sqrt() / 2.0);
         let normal_vector = vector(0.0, 0.0, -1.0);
-        let light = Light {
-            position: point(0.0, 0.0, -10.0),
-            intensity: Color {
-                r: 1.0,
-                g: 1.0,
-                b: 1.0,
-            },
-        };
+        let light = Light::point(point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
         let calculated_color = lighting(material, light, &position, &eye_vector, &normal_vector);
         assert_abs_diff_eq!(
             calculated_color,
@@ -123,14 +243,7 @@ mod tests {
         let position = point(0.0, 0.0, 0.0);
         let eye_vector = vector(0.0, 0.0, -1.0);
         let normal_vector = vector(0.0, 0.0, -1.0);
-        let light = Light {
-            position: point(0.0, 10.0, -10.0),
-            intensity: Color {
-                r: 1.0,
-                g: 1.0,
-                b: 1.0,
-            },
-        };
+        let light = Light::point(point(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
         let calculated_color = lighting(material, light, &position, &eye_vector, &normal_vector);
         assert_abs_diff_eq!(
             calculated_color,
@@ -148,14 +261,7 @@ mod tests {
         let position = point(0.0, 0.0, 0.0);
         let eye_vector = vector(0.0, -2.0_f32.sqrt() / 2.0, -2.0_f32.sqrt() / 2.0);
         let normal_vector = vector(0.0, 0.0, -1.0);
-        let light = Light {
-            position: point(0.0, 10.0, -10.0),
-            intensity: Color {
-                r: 1.0,
-                g: 1.0,
-                b: 1.0,
-            },
-        };
+        let light = Light::point(point(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
         let calculated_color = lighting(material, light, &position, &eye_vector, &normal_vector);
         assert_abs_diff_eq!(
             calculated_color,
@@ -173,14 +279,7 @@ mod tests {
         let position = point(0.0, 0.0, 0.0);
         let eye_vector = vector(0.0, 1.0, -1.0);
         let normal_vector = vector(0.0, 0.0, -1.0);
-        let light = Light {
-            position: point(0.0, 0.0, 10.0),
-            intensity: Color {
-                r: 1.0,
-                g: 1.0,
-                b: 1.0,
-            },
-        };
+        let light = Light::point(point(0.0, 0.0, 10.0), Color::new(1.0, 1.0, 1.0));
         let calculated_color = lighting(material, light, &position, &eye_vector, &normal_vector);
         assert_abs_diff_eq!(
             calculated_color,
@@ -191,4 +290,166 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn lighting_with_a_directional_light_facing_the_surface() {
+        let material = Material::new();
+        let position = point(0.0, 0.0, 0.0);
+        let eye_vector = vector(0.0, 0.0, -1.0);
+        let normal_vector = vector(0.0, 0.0, -1.0);
+        let light = Light::directional(vector(0.0, 0.0, 1.0), Color::new(1.0, 1.0, 1.0));
+        let calculated_color = lighting(material, light, &position, &eye_vector, &normal_vector);
+        assert_abs_diff_eq!(
+            calculated_color,
+            Color {
+                r: 1.9,
+                g: 1.9,
+                b: 1.9
+            }
+        );
+    }
+
+    #[test]
+    fn lighting_outside_the_cone_of_a_spot_light_is_only_ambient() {
+        let material = Material::new();
+        let position = point(0.0, 0.0, 0.0);
+        let eye_vector = vector(0.0, 0.0, -1.0);
+        let normal_vector = vector(0.0, 0.0, -1.0);
+        let light = Light::spot(
+            point(0.0, 0.0, -10.0),
+            vector(1.0, 0.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+            0.1,
+            0.2,
+        );
+        let calculated_color = lighting(material, light, &position, &eye_vector, &normal_vector);
+        assert_abs_diff_eq!(
+            calculated_color,
+            Color {
+                r: 0.1,
+                g: 0.1,
+                b: 0.1
+            }
+        );
+    }
+
+    #[test]
+    fn lighting_a_lambertian_material_has_no_highlight() {
+        let mut material = Material::new();
+        material.model = BsdfModel::Lambertian;
+        let position = point(0.0, 0.0, 0.0);
+        let eye_vector = vector(0.0, 0.0, -1.0);
+        let normal_vector = vector(0.0, 0.0, -1.0);
+        let light = Light::point(point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+        let calculated_color = lighting(material, light, &position, &eye_vector, &normal_vector);
+        assert_abs_diff_eq!(
+            calculated_color,
+            Color {
+                r: 1.0,
+                g: 1.0,
+                b: 1.0
+            },
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn each_model_builds_its_bsdf() {
+        let normal = vector(0.0, 1.0, 0.0);
+        let mut material = Material::new();
+        material.model = BsdfModel::OrenNayar { roughness: 0.0 };
+        assert_abs_diff_eq!(
+            material.bsdf().evaluate(&normal, &normal, &normal),
+            Color::new(0.9 / PI, 0.9 / PI, 0.9 / PI),
+            epsilon = 0.0001
+        );
+        material.model = BsdfModel::BlinnPhong;
+        assert!(material.bsdf().evaluate(&normal, &normal, &normal).r > 0.9 / PI);
+    }
+
+    #[test]
+    fn lighting_a_metal_takes_the_color_of_the_highlight() {
+        let mut material = Material::new();
+        material.color = Color::new(1.0, 0.5, 0.0);
+        material.model = BsdfModel::Microfacet {
+            metallic: 1.0,
+            roughness: 0.3,
+        };
+        let position = point(0.0, 0.0, 0.0);
+        let eye_vector = vector(0.0, 0.0, -1.0);
+        let normal_vector = vector(0.0, 0.0, -1.0);
+        let light = Light::point(point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+        let calculated_color = lighting(material, light, &position, &eye_vector, &normal_vector);
+        assert!(calculated_color.r > 1.0);
+        assert!(calculated_color.r > calculated_color.g);
+        assert!(calculated_color.g > calculated_color.b);
+    }
+
+    #[test]
+    fn glass_seen_from_inside_uses_the_inverse_index() {
+        let mut material = Material::new();
+        material.model = BsdfModel::RoughDielectric {
+            ior: 1.5,
+            roughness: 0.01,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(1.0, 0.2, 0.0).normalize();
+        // Light can enter glass at a grazing angle but not leave it.
+        let transmitted = |front_face: bool| {
+            let bsdf = material.bsdf_for_side(front_face);
+            let mut rng = StdRng::seed_from_u64(0);
+            (0..100)
+                .filter_map(|_| bsdf.sample(&normal, &outgoing, &mut rng))
+                .filter(|sample| dot(&normal, &sample.direction) < 0.0)
+                .count()
+        };
+        assert!(transmitted(true) > 50);
+        assert_eq!(transmitted(false), 0);
+    }
+
+    #[test]
+    fn car_paint_is_a_coat_over_a_metallic_base() {
+        let mut material = Material::new();
+        material.color = Color::new(0.8, 0.0, 0.0);
+        material.model = BsdfModel::ClearCoat {
+            base: Box::new(BsdfModel::Microfacet {
+                metallic: 0.5,
+                roughness: 0.4,
+            }),
+            strength: 1.0,
+            ior: 1.5,
+            roughness: 0.05,
+            thin_film: None,
+        };
+        let normal = vector(0.0, 1.0, 0.0);
+        let outgoing = vector(1.0, 1.0, 0.0).normalize();
+        let mirror = vector(-1.0, 1.0, 0.0).normalize();
+        let off_mirror = vector(0.0, 1.0, 1.0).normalize();
+        let bsdf = material.bsdf();
+        assert!(bsdf.evaluate(&normal, &outgoing, &mirror).g > 1.0);
+        let base_color = bsdf.evaluate(&normal, &outgoing, &off_mirror);
+        assert!(base_color.r > base_color.g);
+    }
+
+    #[test]
+    fn the_default_material_reflects_at_most_the_light_it_receives() {
+        // White furnace: the whole hemisphere above is lit evenly, so the
+        // reflected light is the hemispherical albedo.
+        let bsdf = Material::new().bsdf();
+        let normal = vector(0.0, 0.0, 1.0);
+        let mut rng = StdRng::seed_from_u64(1);
+        for outgoing in [vector(0.0, 0.0, 1.0), vector(0.0, 0.6, 0.8)].iter() {
+            let samples = 20000;
+            let mut total = Color::black();
+            for _ in 0..samples {
+                if let Some(sample) = bsdf.sample(&normal, outgoing, &mut rng) {
+                    let cosine = dot(&normal, &sample.direction).abs();
+                    total = crate::colors::add(total, multiply(&sample.value, cosine / sample.pdf));
+                }
+            }
+            let albedo = multiply(&total, 1.0 / samples as f32);
+            assert!(albedo.r <= 1.01, "reflects {:?}", albedo);
+            assert!(albedo.r > 0.8);
+        }
+    }
 }
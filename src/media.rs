@@ -0,0 +1,196 @@
+use rand::Rng;
+
+use std::f32::consts::PI;
+
+use crate::colors::*;
+use crate::sampling::to_world;
+use crate::tuples::{vector, Tuple};
+
+/// Homogeneous participating medium like fog, smoke or murky water. The
+/// coefficients are the fraction of light absorbed and scattered per unit of
+/// distance, per channel. `anisotropy` is the Henyey-Greenstein `g`: 0
+/// scatters evenly in every direction and values close to 1 mostly forward.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Medium {
+    pub absorption: Color,
+    pub scattering: Color,
+    pub anisotropy: f32,
+}
+
+impl Medium {
+    /// Grey medium that only scatters, `density` times per unit of distance.
+    pub fn fog(density: f32) -> Medium {
+        Medium {
+            absorption: Color::black(),
+            scattering: Color::new(density, density, density),
+            anisotropy: 0.0,
+        }
+    }
+
+    pub fn extinction(self: &Self) -> Color {
+        add(self.absorption.clone(), self.scattering.clone())
+    }
+
+    /// Fraction of the light that crosses `distance` without being absorbed
+    /// or scattered away.
+    pub fn transmittance(self: &Self, distance: f32) -> Color {
+        let extinction = self.extinction();
+        let channel = |sigma: f32| {
+            if sigma <= 0.0 {
+                1.0
+            } else {
+                (-sigma * distance).exp()
+            }
+        };
+        Color::new(
+            channel(extinction.r),
+            channel(extinction.g),
+            channel(extinction.b),
+        )
+    }
+
+    /// Distance to the next scattering event along a ray. The density of a
+    /// channel picked at random is followed, so `distance_pdf` averages all of
+    /// them. Absorption never ends a ray here, it is left to the transmittance.
+    /// Returns infinity when the picked channel doesn't scatter at all.
+    pub fn sample_distance<R: Rng + ?Sized>(self: &Self, rng: &mut R) -> f32 {
+        let sigma = match rng.gen_range(0, 3) {
+            0 => self.scattering.r,
+            1 => self.scattering.g,
+            _ => self.scattering.b,
+        };
+        if sigma <= 0.0 {
+            return f32::INFINITY;
+        }
+        -(1.0 - rng.gen::<f32>()).ln() / sigma
+    }
+
+    /// Density of `sample_distance` stopping at `distance` when `scattered`,
+    /// or probability of going further than `distance` otherwise.
+    pub fn distance_pdf(self: &Self, distance: f32, scattered: bool) -> f32 {
+        let channel = |sigma: f32| {
+            let survival = if sigma <= 0.0 {
+                1.0
+            } else {
+                (-sigma * distance).exp()
+            };
+            if scattered {
+                sigma * survival
+            } else {
+                survival
+            }
+        };
+        (channel(self.scattering.r) + channel(self.scattering.g) + channel(self.scattering.b)) / 3.0
+    }
+}
+
+/// Henyey-Greenstein phase function. `cos_theta` is the cosine between the
+/// direction the light travelled before and after scattering.
+pub fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.max(0.0).sqrt())
+}
+
+/// Direction scattered around `forward` with the Henyey-Greenstein density.
+pub fn sample_henyey_greenstein(forward: &Tuple, g: f32, u1: f32, u2: f32) -> Tuple {
+    let cos_theta = if g.abs() < 0.001 {
+        1.0 - 2.0 * u1
+    } else {
+        let ratio = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+        (1.0 + g * g - ratio * ratio) / (2.0 * g)
+    }
+    .clamp(-1.0, 1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    let local = vector(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+    to_world(&local, forward).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::media::*;
+    use crate::tuples::{dot, magnitude};
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn fog_fades_light_exponentially_with_distance() {
+        let fog = Medium::fog(0.5);
+        assert_abs_diff_eq!(fog.transmittance(0.0), Color::new(1.0, 1.0, 1.0));
+        let expected = (-1.0_f32).exp();
+        assert_abs_diff_eq!(
+            fog.transmittance(2.0),
+            Color::new(expected, expected, expected),
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn colored_absorption_tints_the_light() {
+        let medium = Medium {
+            absorption: Color::new(0.0, 1.0, 2.0),
+            scattering: Color::black(),
+            anisotropy: 0.0,
+        };
+        let transmittance = medium.transmittance(1.0);
+        assert_abs_diff_eq!(transmittance.r, 1.0);
+        assert!(transmittance.g > transmittance.b);
+    }
+
+    #[test]
+    fn sampled_distances_follow_the_transmittance() {
+        let fog = Medium::fog(0.5);
+        let mut rng = StdRng::seed_from_u64(0);
+        let samples = 10000;
+        let further = (0..samples)
+            .filter(|_| fog.sample_distance(&mut rng) > 2.0)
+            .count();
+        assert_abs_diff_eq!(
+            further as f32 / samples as f32,
+            fog.distance_pdf(2.0, false),
+            epsilon = 0.02
+        );
+    }
+
+    #[test]
+    fn a_medium_that_does_not_scatter_never_stops_rays() {
+        let clear = Medium {
+            absorption: Color::new(1.0, 1.0, 1.0),
+            scattering: Color::black(),
+            anisotropy: 0.0,
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(clear.sample_distance(&mut rng), f32::INFINITY);
+    }
+
+    #[test]
+    fn the_phase_function_is_normalized() {
+        for g in [-0.5, 0.0, 0.8].iter() {
+            let steps = 20000;
+            let total: f32 = (0..steps)
+                .map(|i| {
+                    let cos_theta = -1.0 + 2.0 * (i as f32 + 0.5) / steps as f32;
+                    henyey_greenstein(cos_theta, *g) * 2.0 * PI * 2.0 / steps as f32
+                })
+                .sum();
+            assert_abs_diff_eq!(total, 1.0, epsilon = 0.01);
+        }
+    }
+
+    #[test]
+    fn forward_scattering_keeps_going_forward() {
+        let forward = vector(0.0, 0.0, 1.0);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mean_cosine = (0..1000)
+            .map(|_| {
+                let direction = sample_henyey_greenstein(&forward, 0.7, rng.gen(), rng.gen());
+                assert_abs_diff_eq!(magnitude(&direction), 1.0, epsilon = 0.0001);
+                dot(&direction, &forward)
+            })
+            .sum::<f32>()
+            / 1000.0;
+        // The mean cosine of Henyey-Greenstein is g.
+        assert_abs_diff_eq!(mean_cosine, 0.7, epsilon = 0.05);
+    }
+}
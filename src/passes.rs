@@ -0,0 +1,305 @@
+//! Arbitrary output variables: layers rendered along with the image, which
+//! compositors and the denoiser use to rework it without rendering again.
+
+use crate::canvas::*;
+use crate::colors::Color;
+use crate::intersections::hit;
+use crate::materials::BsdfModel;
+use crate::rays::Ray;
+use crate::tuples::{add, dot, mul, Tuple};
+use crate::world::{World, EPSILON};
+
+/// Names of the passes, in the order of their layers.
+pub const PASSES: [&str; 10] = [
+    "beauty",
+    "direct",
+    "indirect",
+    "specular",
+    "normal",
+    "albedo",
+    "shadow",
+    "depth",
+    "object_id",
+    "material_id",
+];
+
+/// Passes of a render, all of the size of the image. Scalar passes have the
+/// same value in the three channels.
+#[derive(Debug)]
+pub struct Passes {
+    pub beauty: Canvas,
+    /// Light emitted by what the camera sees or scattered by it straight
+    /// from the lights.
+    pub direct: Canvas,
+    /// The rest of the light, which bounced more than once.
+    pub indirect: Canvas,
+    /// Light scattered by the glossy part of the surfaces the camera sees,
+    /// directly or not.
+    pub specular: Canvas,
+    /// Outward normal of the first surface in world space, as x, y and z.
+    pub normal: Canvas,
+    /// Color of the first surface.
+    pub albedo: Canvas,
+    /// Share of the lights above the first surface that something hides.
+    pub shadow: Canvas,
+    /// Distance along the ray to the first surface, infinite when there is
+    /// none.
+    pub depth: Canvas,
+    /// Index of the first surface in the objects of the world plus one, 0
+    /// where there is no surface.
+    pub object_id: Canvas,
+    /// Index plus one of the first object with the material of the first
+    /// surface, so objects sharing a material share the ID.
+    pub material_id: Canvas,
+}
+
+impl Passes {
+    pub fn new(width: u32, height: u32) -> Passes {
+        Passes {
+            beauty: build_canvas(width, height),
+            direct: build_canvas(width, height),
+            indirect: build_canvas(width, height),
+            specular: build_canvas(width, height),
+            normal: build_canvas(width, height),
+            albedo: build_canvas(width, height),
+            shadow: build_canvas(width, height),
+            depth: build_canvas(width, height),
+            object_id: build_canvas(width, height),
+            material_id: build_canvas(width, height),
+        }
+    }
+
+    /// The passes with their names, as layers of an OpenEXR image.
+    pub fn layers(self: &Self) -> Vec<(&str, &Canvas)> {
+        let canvases = [
+            &self.beauty,
+            &self.direct,
+            &self.indirect,
+            &self.specular,
+            &self.normal,
+            &self.albedo,
+            &self.shadow,
+            &self.depth,
+            &self.object_id,
+            &self.material_id,
+        ];
+        PASSES
+            .iter()
+            .cloned()
+            .zip(canvases.iter().cloned())
+            .collect()
+    }
+
+    /// Sets the pixel at (`x`, `y`) of every pass, given in the order of
+    /// `PASSES`.
+    pub fn write(self: &mut Self, x: u32, y: u32, values: &[Color]) {
+        let mut canvases = [
+            &mut self.beauty,
+            &mut self.direct,
+            &mut self.indirect,
+            &mut self.specular,
+            &mut self.normal,
+            &mut self.albedo,
+            &mut self.shadow,
+            &mut self.depth,
+            &mut self.object_id,
+            &mut self.material_id,
+        ];
+        for (canvas, value) in canvases.iter_mut().zip(values.iter()) {
+            write_pixel(canvas, x, y, value.clone());
+        }
+    }
+}
+
+/// What the geometric passes record about the first surface along a ray.
+#[derive(Clone, Debug)]
+pub struct Surface {
+    pub depth: f32,
+    pub normal: Color,
+    pub albedo: Color,
+    pub shadow: f32,
+    pub object_id: f32,
+    pub material_id: f32,
+}
+
+/// First surface along `ray`, going through interfaces like the path tracer
+/// does.
+pub fn first_surface(world: &World, ray: &Ray) -> Option<Surface> {
+    let mut ray = ray.clone();
+    let mut depth = 0.0;
+    loop {
+        let intersection = hit(world.intersect(&ray))?;
+        let object = &intersection.object;
+        let point_at = ray.position(intersection.t);
+        depth += intersection.t;
+        if object.material.model == BsdfModel::Interface {
+            ray.origin = add(&point_at, &mul(&ray.direction, EPSILON));
+            depth += EPSILON;
+            continue;
+        }
+
+        let normal = object.normal_at(&point_at).normalize();
+        let facing = if dot(&normal, &ray.direction) <= 0.0 {
+            normal.clone()
+        } else {
+            normal.negate()
+        };
+        let id = |index: Option<usize>| index.map_or(0.0, |index| index as f32 + 1.0);
+        return Some(Surface {
+            depth,
+            normal: Color::new(normal.x(), normal.y(), normal.z()),
+            albedo: object.material.color.clone(),
+            shadow: shadowing(
+                world,
+                &point_at,
+                &facing,
+                ray.time,
+                intersection.object_index,
+            ),
+            object_id: id(intersection.object_index),
+            material_id: id(world
+                .objects
+                .iter()
+                .position(|other| other.material == object.material)),
+        });
+    }
+}
+
+/// Share of the lights above the surface at `point_at` that are hidden from
+/// it, counting every point sampled on emissive objects as a light. Media
+/// dim lights without hiding them.
+fn shadowing(
+    world: &World,
+    point_at: &Tuple,
+    normal: &Tuple,
+    time: f32,
+    object_index: Option<usize>,
+) -> f32 {
+    let lights =
+        world
+            .lights
+            .iter()
+            .cloned()
+            .chain(world.geometry_lights(point_at, time, object_index));
+    let mut above = 0;
+    let mut hidden = 0;
+    for light in lights {
+        let direction = light.direction_from(point_at);
+        if dot(normal, &direction) <= 0.0 {
+            continue;
+        }
+        above += 1;
+        let shadow_ray = Ray {
+            origin: point_at.clone(),
+            direction,
+            time,
+        };
+        if world.transmittance(&shadow_ray, light.distance_from(point_at)) == Color::black() {
+            hidden += 1;
+        }
+    }
+    if above == 0 {
+        0.0
+    } else {
+        hidden as f32 / above as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lights::Light;
+    use crate::passes::*;
+    use crate::spheres::Sphere;
+    use crate::transformations::*;
+    use crate::tuples::{point, vector};
+
+    fn ray_to_origin() -> Ray {
+        Ray {
+            origin: point(0.0, 0.0, -5.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn the_first_surface_is_found_through_interfaces() {
+        let mut world = World::new();
+        let mut volume = Sphere::new();
+        volume.transformation = scale(3.0, 3.0, 3.0);
+        volume.material.model = BsdfModel::Interface;
+        world.objects.push(volume);
+        let mut red = Sphere::new();
+        red.material.color = Color::new(1.0, 0.0, 0.0);
+        world.objects.push(red.clone());
+        red.transformation = translation(5.0, 0.0, 0.0);
+        world.objects.push(red);
+
+        let surface = first_surface(&world, &ray_to_origin()).unwrap();
+        assert_abs_diff_eq!(surface.depth, 4.0, epsilon = 0.001);
+        assert_abs_diff_eq!(surface.normal, Color::new(0.0, 0.0, -1.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(surface.albedo, Color::new(1.0, 0.0, 0.0));
+        assert_abs_diff_eq!(surface.object_id, 2.0);
+        assert_abs_diff_eq!(surface.material_id, 2.0);
+        let miss = Ray {
+            origin: point(0.0, 5.0, -5.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        assert!(first_surface(&world, &miss).is_none());
+    }
+
+    #[test]
+    fn moving_objects_have_their_own_id() {
+        let mut world = World::new();
+        let mut still = Sphere::new();
+        still.transformation = translation(10.0, 0.0, 0.0);
+        world.objects.push(still);
+        let mut moving = Sphere::new();
+        moving.set_closing_transformation(translation(4.0, 0.0, 0.0));
+        world.objects.push(moving);
+        let surface = first_surface(&world, &ray_to_origin()).unwrap();
+        assert_abs_diff_eq!(surface.object_id, 2.0);
+        assert_abs_diff_eq!(surface.material_id, 1.0);
+    }
+
+    #[test]
+    fn the_shadow_mask_counts_the_hidden_lights() {
+        let mut world = World::new();
+        world.objects.push(Sphere::new());
+        let mut blocker = Sphere::new();
+        blocker.transformation = translation(0.0, 0.0, -5.0);
+        world.objects.push(blocker);
+        let white = Color::new(1.0, 1.0, 1.0);
+        world
+            .lights
+            .push(Light::point(point(0.0, 0.0, -10.0), white.clone()));
+        world
+            .lights
+            .push(Light::point(point(-10.0, 0.0, -10.0), white.clone()));
+        // Below the surface, so it doesn't count.
+        world
+            .lights
+            .push(Light::point(point(0.0, 0.0, 10.0), white));
+        let ray = Ray {
+            origin: point(0.0, 0.0, -3.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let surface = first_surface(&world, &ray).unwrap();
+        assert_abs_diff_eq!(surface.shadow, 0.5);
+    }
+
+    #[test]
+    fn passes_are_named_layers() {
+        let mut passes = Passes::new(2, 1);
+        let values: Vec<Color> = (0..PASSES.len())
+            .map(|index| Color::new(index as f32, 0.0, 0.0))
+            .collect();
+        passes.write(1, 0, &values);
+        assert_abs_diff_eq!(pixel_at(&passes.albedo, 1, 0).r, 5.0);
+        let layers = passes.layers();
+        assert_eq!(layers.len(), PASSES.len());
+        assert_eq!(layers[7].0, "depth");
+        assert_abs_diff_eq!(pixel_at(layers[7].1, 1, 0).r, 7.0);
+    }
+}
@@ -0,0 +1,829 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::camera::Camera;
+use crate::canvas::*;
+use crate::colors::*;
+use crate::error::Error;
+use crate::intersections::hit;
+use crate::materials::BsdfModel;
+use crate::media::{henyey_greenstein, sample_henyey_greenstein};
+use crate::passes::{first_surface, Passes};
+use crate::rays::Ray;
+use crate::tuples::{dot, magnitude, mul, sub, Tuple};
+use crate::world::{World, EPSILON};
+
+/// How light sampling and BSDF sampling are weighted against each other when
+/// both can find the same emissive object (multiple importance sampling).
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum MisHeuristic {
+    Balance,
+    Power,
+}
+
+impl MisHeuristic {
+    /// Weight of a sample taken with density `pdf` when the other strategy
+    /// could have taken it with density `other_pdf`.
+    pub fn weight(self: &Self, pdf: f32, other_pdf: f32) -> f32 {
+        let (a, b) = match self {
+            MisHeuristic::Balance => (pdf, other_pdf),
+            MisHeuristic::Power => (pdf * pdf, other_pdf * other_pdf),
+        };
+        if a + b > 0.0 {
+            a / (a + b)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Unbiased Monte Carlo integrator. Bounces are sampled from the BSDF of the
+/// material, lights and emissive objects are sampled directly at
+/// every bounce (next event estimation) and paths are ended with Russian
+/// roulette once they have bounced `roulette_depth` times. Emissive objects
+/// found either way are combined with multiple importance sampling. Rays
+/// crossing a medium may scatter in it, at distances sampled from its
+/// scattering coefficient (free-flight sampling).
+#[derive(Clone, Debug)]
+pub struct PathTracer {
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub roulette_depth: u32,
+    pub heuristic: MisHeuristic,
+}
+
+impl PathTracer {
+    pub fn new(samples_per_pixel: u32) -> PathTracer {
+        PathTracer {
+            samples_per_pixel,
+            max_depth: 16,
+            roulette_depth: 3,
+            heuristic: MisHeuristic::Power,
+        }
+    }
+
+    /// Fails before rendering anything when an object can't be rendered.
+    pub fn render(self: &Self, camera: &Camera, world: &World) -> Result<Canvas, Error> {
+        world.check()?;
+        let rows = render_rows(camera, |x, y, rng| {
+            self.pixel_color(camera, world, x, y, rng)
+        });
+        let mut canvas = build_canvas(camera.width, camera.height);
+        for (y, row) in rows.into_iter().enumerate() {
+            for (x, color) in row.into_iter().enumerate() {
+                write_pixel(&mut canvas, x as u32, y as u32, color);
+            }
+        }
+        Ok(canvas)
+    }
+
+    /// Renders the image along with its passes. The beauty pass is the image
+    /// `render` makes.
+    pub fn render_passes(self: &Self, camera: &Camera, world: &World) -> Result<Passes, Error> {
+        world.check()?;
+        let rows = render_rows(camera, |x, y, rng| {
+            self.pixel_passes(camera, world, x, y, rng)
+        });
+        let mut passes = Passes::new(camera.width, camera.height);
+        for (y, row) in rows.into_iter().enumerate() {
+            for (x, values) in row.into_iter().enumerate() {
+                passes.write(x as u32, y as u32, &values);
+            }
+        }
+        Ok(passes)
+    }
+
+    /// Average of the radiance carried by `samples_per_pixel` rays jittered
+    /// inside the pixel, over the lens and while the shutter is open.
+    pub fn pixel_color<R: Rng>(
+        self: &Self,
+        camera: &Camera,
+        world: &World,
+        x: u32,
+        y: u32,
+        rng: &mut R,
+    ) -> Color {
+        let samples = self.samples_per_pixel.max(1);
+        let total = (0..samples).fold(Color::black(), |color, _| {
+            let ray = sample_ray(camera, x, y, rng);
+            add(color, self.radiance(world, &ray, rng))
+        });
+        multiply(&total, 1.0 / samples as f32)
+    }
+
+    /// Values of every pass at a pixel, in the order of `PASSES`. Depth and
+    /// IDs are those of the ray through the center of the pixel, the other
+    /// passes are averaged over the same rays as the color.
+    fn pixel_passes<R: Rng>(
+        self: &Self,
+        camera: &Camera,
+        world: &World,
+        x: u32,
+        y: u32,
+        rng: &mut R,
+    ) -> Vec<Color> {
+        let samples = self.samples_per_pixel.max(1);
+        let mut values = vec![Color::black(); 7];
+        for _ in 0..samples {
+            let ray = sample_ray(camera, x, y, rng);
+            let path = self.trace(world, &ray, rng);
+            let indirect = crate::colors::sub(path.radiance.clone(), path.direct.clone());
+            let (normal, albedo, shadow) = match first_surface(world, &ray) {
+                Some(surface) => (surface.normal, surface.albedo, surface.shadow),
+                None => (Color::black(), Color::black(), 0.0),
+            };
+            let sample = [
+                path.radiance,
+                path.direct,
+                indirect,
+                path.specular,
+                normal,
+                albedo,
+                Color::new(shadow, shadow, shadow),
+            ];
+            for (value, sample) in values.iter_mut().zip(sample.iter()) {
+                *value = add(value.clone(), multiply(sample, 1.0 / samples as f32));
+            }
+        }
+
+        let center = first_surface(world, &camera.ray_for_pixel(x, y));
+        let (depth, object_id, material_id) = match center {
+            Some(surface) => (surface.depth, surface.object_id, surface.material_id),
+            None => (f32::INFINITY, 0.0, 0.0),
+        };
+        for value in [depth, object_id, material_id].iter() {
+            values.push(Color::new(*value, *value, *value));
+        }
+        values
+    }
+
+    /// Light arriving at the origin of the ray from its direction.
+    pub fn radiance<R: Rng>(self: &Self, world: &World, ray: &Ray, rng: &mut R) -> Color {
+        self.trace(world, ray, rng).radiance
+    }
+
+    fn trace<R: Rng>(self: &Self, world: &World, ray: &Ray, rng: &mut R) -> PathSample {
+        let mut path = PathSample {
+            radiance: Color::black(),
+            direct: Color::black(),
+            specular: Color::black(),
+        };
+        // Part of the light found after the first bounce that the first
+        // surface reflected or transmitted glossily.
+        let mut glossy_share = Color::black();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = ray.clone();
+        // Density of the BSDF or phase function sample that produced the
+        // current ray, and where it was taken. Camera rays have none, so what
+        // they see is always counted in full.
+        let mut bsdf_pdf: Option<f32> = None;
+        let mut vertex = ray.origin.clone();
+        let mut depth = 0;
+
+        while depth < self.max_depth {
+            let intersection = hit(world.intersect(&ray));
+            let distance = intersection.as_ref().map_or(f32::INFINITY, |i| i.t);
+
+            // Free-flight sampling: the medium may scatter the ray before it
+            // reaches the next surface.
+            if let Some(medium) = world.medium_at(&ray.origin, ray.time) {
+                let travelled = medium.sample_distance(rng);
+                if travelled < distance {
+                    let point_at = ray.position(travelled);
+                    let scattered =
+                        hadamard_product(&medium.scattering, &medium.transmittance(travelled));
+                    throughput = hadamard_product(
+                        &throughput,
+                        &multiply(&scattered, 1.0 / medium.distance_pdf(travelled, true)),
+                    );
+
+                    let anisotropy = medium.anisotropy;
+                    let forward = ray.direction.clone();
+                    let phase = |direction: &Tuple| {
+                        let value = henyey_greenstein(dot(&forward, direction), anisotropy);
+                        (Color::new(value, value, value), Color::black(), value)
+                    };
+                    let (direct, _) = self.direct_lighting(world, &point_at, ray.time, &phase, rng);
+                    path.add(hadamard_product(&throughput, &direct), depth, &glossy_share);
+
+                    // Phase function samples carry a weight of one.
+                    let direction =
+                        sample_henyey_greenstein(&forward, anisotropy, rng.gen(), rng.gen());
+                    bsdf_pdf = Some(henyey_greenstein(dot(&forward, &direction), anisotropy));
+                    vertex = point_at.clone();
+                    ray = Ray {
+                        origin: point_at,
+                        direction,
+                        time: ray.time,
+                    };
+                    depth += 1;
+                    if !self.survives_roulette(depth, &mut throughput, rng) {
+                        break;
+                    }
+                    continue;
+                }
+                throughput = hadamard_product(
+                    &throughput,
+                    &multiply(
+                        &medium.transmittance(distance),
+                        1.0 / medium.distance_pdf(distance, false),
+                    ),
+                );
+            }
+
+            let intersection = match intersection {
+                Some(intersection) => intersection,
+                None => break,
+            };
+            let object = &intersection.object;
+            let material = &object.material;
+            let point_at = ray.position(intersection.t);
+
+            // Interfaces only change the medium the ray travels through.
+            if material.model == BsdfModel::Interface {
+                ray = Ray {
+                    origin: crate::tuples::add(&point_at, &mul(&ray.direction, EPSILON)),
+                    direction: ray.direction,
+                    time: ray.time,
+                };
+                continue;
+            }
+
+            let outward_normal = object.normal_at(&point_at).normalize();
+            let front_face = dot(&outward_normal, &ray.direction) <= 0.0;
+            let normal = if front_face {
+                outward_normal.clone()
+            } else {
+                outward_normal.negate()
+            };
+
+            if material.emission != Color::black() {
+                let weight = match bsdf_pdf {
+                    None => 1.0,
+                    Some(pdf) => {
+                        let distance = magnitude(&sub(&point_at, &vertex));
+                        let cosine_light = -dot(&outward_normal, &ray.direction);
+                        let light_pdf = if cosine_light > 0.0 {
+                            object.surface_pdf(&point_at) * distance * distance / cosine_light
+                        } else {
+                            0.0
+                        };
+                        self.heuristic.weight(pdf, light_pdf)
+                    }
+                };
+                let emitted = multiply(&material.emission, weight);
+                path.add(
+                    hadamard_product(&throughput, &emitted),
+                    depth,
+                    &glossy_share,
+                );
+            }
+
+            let bsdf = material.bsdf_for_side(front_face);
+            let outgoing = ray.direction.negate();
+            let first = depth == 0;
+            let scatter = |direction: &Tuple| {
+                // Transmitted directions are below the surface.
+                let cosine = dot(&normal, direction).abs();
+                let value = multiply(&bsdf.evaluate(&normal, &outgoing, direction), cosine);
+                let glossy = if first {
+                    let diffuse = bsdf.evaluate_diffuse(&normal, &outgoing, direction);
+                    crate::colors::sub(value.clone(), multiply(&diffuse, cosine))
+                } else {
+                    Color::black()
+                };
+                (value, glossy, bsdf.pdf(&normal, &outgoing, direction))
+            };
+            let (direct, glossy) = self.direct_lighting(world, &point_at, ray.time, &scatter, rng);
+            path.add(hadamard_product(&throughput, &direct), depth, &glossy_share);
+            if first {
+                path.specular = add(path.specular, hadamard_product(&throughput, &glossy));
+            }
+
+            let sample = match bsdf.sample(&normal, &outgoing, rng) {
+                Some(sample) if sample.pdf > 0.0 => sample,
+                _ => break,
+            };
+            if first {
+                let diffuse = bsdf.evaluate_diffuse(&normal, &outgoing, &sample.direction);
+                glossy_share = share(
+                    &crate::colors::sub(sample.value.clone(), diffuse),
+                    &sample.value,
+                );
+            }
+            let cosine = dot(&normal, &sample.direction).abs();
+            throughput =
+                hadamard_product(&throughput, &multiply(&sample.value, cosine / sample.pdf));
+
+            depth += 1;
+            if !self.survives_roulette(depth, &mut throughput, rng) {
+                break;
+            }
+
+            bsdf_pdf = Some(sample.pdf);
+            vertex = point_at.clone();
+            let side = dot(&normal, &sample.direction).signum();
+            ray = Ray {
+                origin: crate::tuples::add(&point_at, &mul(&normal, side * EPSILON)),
+                direction: sample.direction,
+                time: ray.time,
+            };
+        }
+
+        path
+    }
+
+    /// Russian roulette once the path has bounced `roulette_depth` times.
+    /// Surviving paths are boosted to make up for the ones that were ended.
+    fn survives_roulette<R: Rng>(
+        self: &Self,
+        depth: u32,
+        throughput: &mut Color,
+        rng: &mut R,
+    ) -> bool {
+        if depth < self.roulette_depth {
+            return true;
+        }
+        let survival = throughput.r.max(throughput.g).max(throughput.b).min(0.95);
+        if survival <= 0.0 || rng.gen::<f32>() >= survival {
+            return false;
+        }
+        *throughput = multiply(throughput, 1.0 / survival);
+        true
+    }
+
+    /// Light scattered at `point_at` and `time` coming straight from every
+    /// light and from one sampled point of every emissive object, dimmed by
+    /// whatever lies in between. `scatter` gives, for a direction towards the light, the value
+    /// of the BSDF times the cosine (or of the phase function), the glossy part
+    /// of that value and the density of sampling that direction. The light
+    /// scattered by the glossy part is returned as well. Samples of emissive objects are weighted
+    /// against the chance of finding them that way. Samples on the shaded
+    /// object itself face away from the point and are discarded like any back
+    /// facing one.
+    fn direct_lighting<R: Rng>(
+        self: &Self,
+        world: &World,
+        point_at: &Tuple,
+        time: f32,
+        scatter: &dyn Fn(&Tuple) -> (Color, Color, f32),
+        rng: &mut R,
+    ) -> (Color, Color) {
+        let mut reflected = Color::black();
+        let mut glossy = Color::black();
+
+        // Point-like lights can't be hit by a bounce, so their samples take
+        // the whole weight.
+        for light in world.lights.iter() {
+            let direction = light.direction_from(point_at);
+            let (value, glossy_value, _) = scatter(&direction);
+            if value == Color::black() {
+                continue;
+            }
+            let shadow_ray = Ray {
+                origin: point_at.clone(),
+                direction,
+                time,
+            };
+            let transmittance = world.transmittance(&shadow_ray, light.distance_from(point_at));
+            let incoming = hadamard_product(&light.intensity_at(point_at), &transmittance);
+            reflected = add(reflected, hadamard_product(&value, &incoming));
+            glossy = add(glossy, hadamard_product(&glossy_value, &incoming));
+        }
+
+        for object in world.objects.iter() {
+            if object.material.emission == Color::black() {
+                continue;
+            }
+            let sample = object.at_time(time).sample_surface(rng.gen(), rng.gen());
+            let to_light = sub(&sample.point, point_at);
+            let distance = magnitude(&to_light);
+            if distance <= 0.0 {
+                continue;
+            }
+            let direction = to_light.normalize();
+            let cosine_light = -dot(&sample.normal, &direction);
+            let (value, glossy_value, scatter_pdf) = scatter(&direction);
+            if value == Color::black() || cosine_light <= 0.0 {
+                continue;
+            }
+            let shadow_ray = Ray {
+                origin: point_at.clone(),
+                direction,
+                time,
+            };
+            let transmittance = world.transmittance(&shadow_ray, distance);
+            let light_pdf = distance * distance / (cosine_light * sample.area);
+            let mis_weight = self.heuristic.weight(light_pdf, scatter_pdf);
+            let incoming = multiply(
+                &hadamard_product(&object.material.emission, &transmittance),
+                mis_weight / light_pdf,
+            );
+            reflected = add(reflected, hadamard_product(&value, &incoming));
+            glossy = add(glossy, hadamard_product(&glossy_value, &incoming));
+        }
+
+        (reflected, glossy)
+    }
+}
+
+/// Light brought back by a path, along with the parts of it kept apart in
+/// the passes.
+struct PathSample {
+    radiance: Color,
+    /// Light emitted at the first vertex or scattered there straight from
+    /// the lights.
+    direct: Color,
+    /// Light scattered by the glossy part of the first surface, straight
+    /// from the lights or after more bounces.
+    specular: Color,
+}
+
+impl PathSample {
+    /// Adds light found at `depth`, of which the first surface scattered
+    /// `glossy_share` glossily when it was found after it.
+    fn add(self: &mut Self, light: Color, depth: u32, glossy_share: &Color) {
+        if depth == 0 {
+            self.direct = add(self.direct.clone(), light.clone());
+        } else {
+            let glossy = hadamard_product(&light, glossy_share);
+            self.specular = add(self.specular.clone(), glossy);
+        }
+        self.radiance = add(self.radiance.clone(), light);
+    }
+}
+
+/// Part of `whole` that `part` makes in each channel.
+fn share(part: &Color, whole: &Color) -> Color {
+    let channel = |part: f32, whole: f32| {
+        if whole > 0.0 {
+            (part / whole).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    };
+    Color::new(
+        channel(part.r, whole.r),
+        channel(part.g, whole.g),
+        channel(part.b, whole.b),
+    )
+}
+
+/// Ray through a random point of the pixel and of the lens, at a random time
+/// while the shutter is open.
+fn sample_ray<R: Rng>(camera: &Camera, x: u32, y: u32, rng: &mut R) -> Ray {
+    let mut ray = camera.ray_for_lens_sample(x, y, (rng.gen(), rng.gen()), (rng.gen(), rng.gen()));
+    ray.time = rng.gen();
+    ray
+}
+
+/// Rows of the values `pixel` gives for every pixel of the camera. Rows are
+/// rendered in parallel; every pixel seeds its own generator so the image
+/// doesn't depend on the number of threads.
+fn render_rows<T, F>(camera: &Camera, pixel: F) -> Vec<Vec<T>>
+where
+    T: Send,
+    F: Fn(u32, u32, &mut StdRng) -> T + Sync,
+{
+    (0..camera.height)
+        .into_par_iter()
+        .map(|y| {
+            (0..camera.width)
+                .map(|x| {
+                    let seed = (y as u64) * (camera.width as u64) + x as u64;
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    pixel(x, y, &mut rng)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lights::Light;
+    use crate::materials::BsdfModel;
+    use crate::media::Medium;
+    use crate::path_tracer::*;
+    use crate::spheres::Sphere;
+    use crate::transformations::*;
+    use crate::tuples::{point, vector};
+
+    use std::f32::consts::PI;
+
+    fn ray_to_origin() -> Ray {
+        Ray {
+            origin: point(0.0, 0.0, -5.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn an_empty_world_is_black() {
+        let world = World::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        let color = PathTracer::new(1).radiance(&world, &ray_to_origin(), &mut rng);
+        assert_abs_diff_eq!(color, Color::black());
+    }
+
+    #[test]
+    fn emissive_objects_are_seen_directly() {
+        let mut world = World::new();
+        let mut lamp = Sphere::new();
+        lamp.material.emission = Color::new(2.0, 1.0, 0.5);
+        lamp.material.color = Color::black();
+        world.objects.push(lamp);
+        let mut rng = StdRng::seed_from_u64(0);
+        let color = PathTracer::new(1).radiance(&world, &ray_to_origin(), &mut rng);
+        assert_abs_diff_eq!(color, Color::new(2.0, 1.0, 0.5));
+    }
+
+    #[test]
+    fn a_lit_diffuse_sphere_matches_the_lambert_law() {
+        let mut world = World::new();
+        let mut sphere = Sphere::new();
+        sphere.material.model = BsdfModel::Lambertian;
+        world.objects.push(sphere);
+        world.lights.push(Light::point(
+            point(0.0, 0.0, -10.0),
+            Color::new(1.0, 1.0, 1.0),
+        ));
+        let mut tracer = PathTracer::new(1);
+        tracer.max_depth = 1;
+        let mut rng = StdRng::seed_from_u64(0);
+        let color = tracer.radiance(&world, &ray_to_origin(), &mut rng);
+        assert_abs_diff_eq!(
+            color,
+            Color::new(0.9 / PI, 0.9 / PI, 0.9 / PI),
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn lights_behind_an_object_cast_shadows() {
+        let mut world = World::new();
+        world.objects.push(Sphere::new());
+        let mut blocker = Sphere::new();
+        blocker.transformation = translation(0.0, 0.0, -5.0);
+        world.objects.push(blocker);
+        world.lights.push(Light::point(
+            point(0.0, 0.0, -10.0),
+            Color::new(1.0, 1.0, 1.0),
+        ));
+        let mut tracer = PathTracer::new(1);
+        tracer.max_depth = 1;
+        let mut rng = StdRng::seed_from_u64(0);
+        let ray = Ray {
+            origin: point(0.0, 0.0, -2.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let color = tracer.radiance(&world, &ray, &mut rng);
+        assert_abs_diff_eq!(color, Color::black());
+    }
+
+    #[test]
+    fn rendering_is_deterministic() {
+        let mut world = World::new();
+        let mut sphere = Sphere::new();
+        sphere.transformation = translation(0.0, 0.0, 3.0);
+        world.objects.push(sphere);
+        world.lights.push(Light::point(
+            point(-10.0, 10.0, -10.0),
+            Color::new(1.0, 1.0, 1.0),
+        ));
+        let camera = Camera::new(4, 4, PI / 3.0);
+        let tracer = PathTracer::new(4);
+        let first = tracer.render(&camera, &world).unwrap();
+        let second = tracer.render(&camera, &world).unwrap();
+        assert_eq!(format!("{:?}", first), format!("{:?}", second));
+    }
+
+    #[test]
+    fn passes_split_the_rendered_image() {
+        let mut world = World::new();
+        let mut sphere = Sphere::new();
+        sphere.transformation = translation(0.0, 0.0, 3.0);
+        world.objects.push(sphere);
+        let mut floor = Sphere::new();
+        floor.transformation = translation(0.0, -101.0, 3.0).dot(&scale(100.0, 100.0, 100.0));
+        floor.material.model = BsdfModel::Lambertian;
+        world.objects.push(floor);
+        world.lights.push(Light::point(
+            point(-10.0, 10.0, -10.0),
+            Color::new(1.0, 1.0, 1.0),
+        ));
+        let camera = Camera::new(6, 6, PI / 3.0);
+        let tracer = PathTracer::new(2);
+        let passes = tracer.render_passes(&camera, &world).unwrap();
+        let image = tracer.render(&camera, &world).unwrap();
+        assert_eq!(format!("{:?}", passes.beauty), format!("{:?}", image));
+
+        let center = |canvas: &Canvas| pixel_at(canvas, 3, 3);
+        let parts = add(center(&passes.direct), center(&passes.indirect));
+        assert_abs_diff_eq!(parts, center(&passes.beauty), epsilon = 0.0001);
+        assert!(center(&passes.specular).r > 0.0);
+        assert!(center(&passes.specular).r < center(&passes.beauty).r);
+        assert_abs_diff_eq!(center(&passes.object_id).r, 1.0);
+        // The sky has no surface.
+        assert_eq!(pixel_at(&passes.depth, 0, 0).r, f32::INFINITY);
+        assert_abs_diff_eq!(pixel_at(&passes.object_id, 0, 0).r, 0.0);
+    }
+
+    #[test]
+    fn mis_weights_of_both_strategies_add_up_to_one() {
+        for heuristic in [MisHeuristic::Balance, MisHeuristic::Power].iter() {
+            let weight = heuristic.weight(0.3, 1.7) + heuristic.weight(1.7, 0.3);
+            assert_abs_diff_eq!(weight, 1.0, epsilon = 0.0001);
+        }
+    }
+
+    #[test]
+    fn the_power_heuristic_favours_the_most_likely_strategy() {
+        let balance = MisHeuristic::Balance.weight(2.0, 1.0);
+        let power = MisHeuristic::Power.weight(2.0, 1.0);
+        assert!(power > balance);
+        assert_abs_diff_eq!(power, 0.8);
+    }
+
+    #[test]
+    fn both_heuristics_converge_to_the_same_image() {
+        let mut world = World::new();
+        world.objects.push(Sphere::new());
+        let mut lamp = Sphere::new();
+        lamp.transformation = translation(0.0, 0.0, -3.0).dot(&scale(0.5, 0.5, 0.5));
+        lamp.material.emission = Color::new(4.0, 4.0, 4.0);
+        world.objects.push(lamp);
+        let ray = Ray {
+            origin: point(1.5, 0.0, -1.5),
+            direction: vector(-1.0, 0.0, 1.0).normalize(),
+            time: 0.0,
+        };
+
+        let mut estimates = vec![];
+        for heuristic in [MisHeuristic::Balance, MisHeuristic::Power].iter() {
+            let mut tracer = PathTracer::new(1);
+            tracer.max_depth = 2;
+            tracer.heuristic = *heuristic;
+            let mut rng = StdRng::seed_from_u64(7);
+            let samples = 4000;
+            let total = (0..samples).fold(Color::black(), |color, _| {
+                add(color, tracer.radiance(&world, &ray, &mut rng))
+            });
+            estimates.push(multiply(&total, 1.0 / samples as f32));
+        }
+        assert!(estimates[0].r > 0.0);
+        assert_abs_diff_eq!(estimates[0], estimates[1], epsilon = 0.05 * estimates[0].r);
+    }
+
+    #[test]
+    fn light_goes_through_clear_glass() {
+        let mut world = World::new();
+        let mut glass = Sphere::new();
+        glass.material.model = BsdfModel::RoughDielectric {
+            ior: 1.5,
+            roughness: 0.0,
+        };
+        world.objects.push(glass);
+        let mut lamp = Sphere::new();
+        lamp.transformation = translation(0.0, 0.0, 5.0);
+        lamp.material.emission = Color::new(1.0, 1.0, 1.0);
+        lamp.material.color = Color::black();
+        world.objects.push(lamp);
+
+        let mut rng = StdRng::seed_from_u64(2);
+        let samples = 200;
+        let total = (0..samples).fold(Color::black(), |color, _| {
+            add(
+                color,
+                PathTracer::new(1).radiance(&world, &ray_to_origin(), &mut rng),
+            )
+        });
+        let seen = multiply(&total, 1.0 / samples as f32);
+        // Both interfaces reflect 4% at normal incidence.
+        assert_abs_diff_eq!(seen.r, 0.96 * 0.96, epsilon = 0.05);
+    }
+
+    #[test]
+    fn fog_dims_what_is_behind_it() {
+        let mut world = World::new();
+        let mut lamp = Sphere::new();
+        lamp.material.emission = Color::new(1.0, 1.0, 1.0);
+        lamp.material.color = Color::black();
+        world.objects.push(lamp);
+        world.fog = Some(Medium {
+            absorption: Color::new(0.25, 0.25, 0.25),
+            scattering: Color::black(),
+            anisotropy: 0.0,
+        });
+        let mut rng = StdRng::seed_from_u64(0);
+        let color = PathTracer::new(1).radiance(&world, &ray_to_origin(), &mut rng);
+        let expected = (-1.0_f32).exp();
+        assert_abs_diff_eq!(
+            color,
+            Color::new(expected, expected, expected),
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn fog_scatters_light_towards_the_camera() {
+        // Nothing to see along the ray, but the fog around it is lit.
+        let mut world = World::new();
+        world.fog = Some(Medium::fog(0.1));
+        world.lights.push(Light::point(
+            point(0.0, 2.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        ));
+        let mut rng = StdRng::seed_from_u64(0);
+        let tracer = PathTracer::new(1);
+        let samples = 500;
+        let total = (0..samples).fold(Color::black(), |color, _| {
+            add(color, tracer.radiance(&world, &ray_to_origin(), &mut rng))
+        });
+        assert!(total.r / samples as f32 > 0.01);
+    }
+
+    #[test]
+    fn shadows_in_fog_make_light_shafts() {
+        let mut world = World::new();
+        world.fog = Some(Medium::fog(0.1));
+        world.lights.push(Light::point(
+            point(0.0, 10.0, 0.0),
+            Color::new(100.0, 100.0, 100.0),
+        ));
+        let mut blocker = Sphere::new();
+        blocker.transformation = translation(0.0, 5.0, 0.0).dot(&scale(2.0, 0.1, 2.0));
+        world.objects.push(blocker);
+        let mut backstop = Sphere::new();
+        backstop.transformation = translation(0.0, 0.0, 103.0).dot(&scale(100.0, 100.0, 100.0));
+        backstop.material.color = Color::black();
+        backstop.material.model = BsdfModel::Lambertian;
+        world.objects.push(backstop);
+        // Single scattering only, light bouncing around in the fog would
+        // soften the shafts.
+        let mut tracer = PathTracer::new(1);
+        tracer.max_depth = 1;
+        let in_fog = |x: f32| {
+            let ray = Ray {
+                origin: point(x, 0.0, -5.0),
+                direction: vector(0.0, 0.0, 1.0),
+                time: 0.0,
+            };
+            let mut rng = StdRng::seed_from_u64(4);
+            (0..400)
+                .fold(Color::black(), |color, _| {
+                    add(color, tracer.radiance(&world, &ray, &mut rng))
+                })
+                .r
+        };
+        // Straight under the blocker the fog only gets the light scattered
+        // around, next to it the light shines directly.
+        assert!(in_fog(6.0) > 2.0 * in_fog(0.0));
+    }
+
+    #[test]
+    fn volumes_tint_the_light_going_through_them() {
+        let mut world = World::new();
+        let mut lamp = Sphere::new();
+        lamp.transformation = translation(0.0, 0.0, 5.0);
+        lamp.material.emission = Color::new(1.0, 1.0, 1.0);
+        world.objects.push(lamp);
+        let mut smoke = Sphere::new();
+        smoke.material.model = BsdfModel::Interface;
+        smoke.material.medium = Some(Medium {
+            absorption: Color::new(0.0, 0.5, 1.0),
+            scattering: Color::black(),
+            anisotropy: 0.0,
+        });
+        world.objects.push(smoke);
+        let mut rng = StdRng::seed_from_u64(0);
+        let color = PathTracer::new(1).radiance(&world, &ray_to_origin(), &mut rng);
+        assert_abs_diff_eq!(
+            color,
+            Color::new(1.0, (-1.0_f32).exp(), (-2.0_f32).exp()),
+            epsilon = 0.001
+        );
+    }
+
+    #[test]
+    fn moving_objects_are_blurred() {
+        let mut world = World::new();
+        let mut lamp = Sphere::new();
+        lamp.material.emission = Color::new(1.0, 1.0, 1.0);
+        lamp.set_closing_transformation(translation(4.0, 0.0, 0.0));
+        world.objects.push(lamp);
+        let tracer = PathTracer::new(1);
+        let mut rng = StdRng::seed_from_u64(0);
+        let samples = 2000;
+        let total = (0..samples).fold(Color::black(), |color, _| {
+            let mut ray = ray_to_origin();
+            ray.time = rng.gen();
+            add(color, tracer.radiance(&world, &ray, &mut rng))
+        });
+        // The lamp crosses the ray during the first quarter of the exposure.
+        assert_abs_diff_eq!(total.r / samples as f32, 0.25, epsilon = 0.03);
+    }
+}
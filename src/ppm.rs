@@ -0,0 +1,314 @@
+//! Portable pixmaps, the plain text P3 and the binary P6 flavours. The
+//! conversions write and read channels as they are, scaled to 0..255, while
+//! files are encoded in a color space.
+
+use std::fs;
+
+use crate::canvas::*;
+use crate::color_spaces::ColorSpace;
+use crate::colors::Color;
+use crate::error::Error;
+
+/// Longest line of a P3 file; some readers don't take more.
+const MAX_LINE: usize = 70;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum PpmFormat {
+    /// Plain text, easy to read and diff.
+    P3,
+    /// Binary, a byte per channel.
+    P6,
+}
+
+pub fn canvas_to_ppm(canvas: &Canvas, format: PpmFormat) -> Vec<u8> {
+    let magic = match format {
+        PpmFormat::P3 => "P3",
+        PpmFormat::P6 => "P6",
+    };
+    let mut ppm = format!("{}\n{} {}\n255\n", magic, canvas.width(), canvas.height()).into_bytes();
+    for y in 0..canvas.height() {
+        let row = (0..canvas.width()).flat_map(|x| {
+            let color = pixel_at(canvas, x, y);
+            vec![to_byte(color.r), to_byte(color.g), to_byte(color.b)]
+        });
+        match format {
+            PpmFormat::P3 => {
+                // Every row starts a line, and lines are wrapped between values.
+                let mut line = String::new();
+                for value in row {
+                    let value = value.to_string();
+                    if !line.is_empty() && line.len() + 1 + value.len() > MAX_LINE {
+                        ppm.extend(line.bytes().chain(Some(b'\n')));
+                        line.clear();
+                    }
+                    if !line.is_empty() {
+                        line.push(' ');
+                    }
+                    line.push_str(&value);
+                }
+                ppm.extend(line.bytes().chain(Some(b'\n')));
+            }
+            PpmFormat::P6 => ppm.extend(row),
+        }
+    }
+    ppm
+}
+
+pub fn export_ppm(
+    canvas: &Canvas,
+    filename: &str,
+    format: PpmFormat,
+    color_space: ColorSpace,
+) -> Result<(), Error> {
+    let encoded = color_space.encode_canvas(canvas);
+    fs::write(filename, canvas_to_ppm(&encoded, format)).map_err(|source| Error::Io {
+        filename: filename.to_string(),
+        source,
+    })
+}
+
+/// Reads a P3 or P6 pixmap with any maximum value, including the two bytes
+/// per channel ones. Comments are skipped.
+pub fn ppm_to_canvas(ppm: &[u8]) -> Result<Canvas, Error> {
+    let mut reader = Reader { ppm, position: 0 };
+    let format = match reader.token()? {
+        "P3" => PpmFormat::P3,
+        "P6" => PpmFormat::P6,
+        magic => return Err(Error::Ppm(format!("unknown format '{}'", magic))),
+    };
+    let width = reader.number("width")?;
+    let height = reader.number("height")?;
+    let maximum = reader.number("maximum value")?;
+    if maximum == 0 || maximum > 65535 {
+        return Err(Error::Ppm(format!(
+            "maximum value {} out of range",
+            maximum
+        )));
+    }
+    if format == PpmFormat::P6 {
+        // A single whitespace separates the header from the pixels.
+        reader.position += 1;
+    }
+    // The size comes from the file, so it is checked against what follows
+    // before allocating anything. Plain values take at least a digit and a
+    // space each.
+    let value_size = match format {
+        PpmFormat::P3 => 2,
+        PpmFormat::P6 if maximum > 255 => 2,
+        PpmFormat::P6 => 1,
+    };
+    let needed = (width as u64)
+        .checked_mul(height as u64)
+        .and_then(|pixels| pixels.checked_mul(3 * value_size))
+        .map(|needed| match format {
+            PpmFormat::P3 => needed.saturating_sub(1),
+            PpmFormat::P6 => needed,
+        });
+    let remaining = ppm.len().saturating_sub(reader.position) as u64;
+    if !matches!(needed, Some(needed) if needed <= remaining) {
+        return Err(Error::Ppm("the file ends too soon".to_string()));
+    }
+    let mut canvas = build_canvas(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let mut channels = [0.0; 3];
+            for channel in channels.iter_mut() {
+                let value = match format {
+                    PpmFormat::P3 => reader.number("color value")?,
+                    PpmFormat::P6 => reader.binary(maximum > 255)?,
+                };
+                if value > maximum {
+                    return Err(Error::Ppm(format!(
+                        "color value {} above the maximum {}",
+                        value, maximum
+                    )));
+                }
+                *channel = value as f32 / maximum as f32;
+            }
+            write_pixel(
+                &mut canvas,
+                x,
+                y,
+                Color::new(channels[0], channels[1], channels[2]),
+            );
+        }
+    }
+    Ok(canvas)
+}
+
+/// Reads the linear colors of a file encoded in `color_space`.
+pub fn import_ppm(filename: &str, color_space: ColorSpace) -> Result<Canvas, Error> {
+    let ppm = fs::read(filename).map_err(|source| Error::Io {
+        filename: filename.to_string(),
+        source,
+    })?;
+    Ok(color_space.decode_canvas(&ppm_to_canvas(&ppm)?))
+}
+
+struct Reader<'a> {
+    ppm: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Next word of text, skipping whitespace and comments.
+    fn token(self: &mut Self) -> Result<&'a str, Error> {
+        loop {
+            match self.ppm.get(self.position) {
+                Some(byte) if byte.is_ascii_whitespace() => self.position += 1,
+                Some(b'#') => {
+                    while matches!(self.ppm.get(self.position), Some(&byte) if byte != b'\n') {
+                        self.position += 1;
+                    }
+                }
+                Some(_) => break,
+                None => return Err(Error::Ppm("the file ends too soon".to_string())),
+            }
+        }
+        let start = self.position;
+        while matches!(self.ppm.get(self.position), Some(byte) if !byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.ppm[start..self.position])
+            .map_err(|_| Error::Ppm("the header is not text".to_string()))
+    }
+
+    fn number(self: &mut Self, what: &str) -> Result<u32, Error> {
+        let token = self.token()?;
+        token
+            .parse()
+            .map_err(|_| Error::Ppm(format!("expected the {}, found '{}'", what, token)))
+    }
+
+    /// Next binary value, of one byte or two big endian ones.
+    fn binary(self: &mut Self, wide: bool) -> Result<u32, Error> {
+        let size = if wide { 2 } else { 1 };
+        let bytes = self
+            .ppm
+            .get(self.position..self.position + size)
+            .ok_or_else(|| Error::Ppm("the file ends too soon".to_string()))?;
+        self.position += size;
+        Ok(bytes
+            .iter()
+            .fold(0, |value, &byte| value << 8 | byte as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppm::*;
+
+    fn text(ppm: Vec<u8>) -> String {
+        String::from_utf8(ppm).unwrap()
+    }
+
+    #[test]
+    fn plain_pixmaps_have_a_header_and_clamped_values() {
+        let mut canvas = build_canvas(5, 3);
+        write_pixel(&mut canvas, 0, 0, Color::new(1.5, 0.0, 0.0));
+        write_pixel(&mut canvas, 2, 1, Color::new(0.0, 0.5, 0.0));
+        write_pixel(&mut canvas, 4, 2, Color::new(-0.5, 0.0, 1.0));
+        assert_eq!(
+            text(canvas_to_ppm(&canvas, PpmFormat::P3)),
+            "P3\n5 3\n255\n\
+             255 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n\
+             0 0 0 0 0 0 0 128 0 0 0 0 0 0 0\n\
+             0 0 0 0 0 0 0 0 0 0 0 0 0 0 255\n"
+        );
+    }
+
+    #[test]
+    fn plain_lines_are_wrapped_at_70_characters() {
+        let mut canvas = build_canvas(10, 2);
+        for y in 0..2 {
+            for x in 0..10 {
+                write_pixel(&mut canvas, x, y, Color::new(1.0, 0.8, 0.6));
+            }
+        }
+        let ppm = text(canvas_to_ppm(&canvas, PpmFormat::P3));
+        let lines: Vec<&str> = ppm.lines().skip(3).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204",
+                "153 255 204 153 255 204 153 255 204 153 255 204 153",
+                "255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204",
+                "153 255 204 153 255 204 153 255 204 153 255 204 153",
+            ]
+        );
+        assert!(ppm.ends_with('\n'));
+    }
+
+    #[test]
+    fn both_formats_read_back_what_was_written() {
+        let mut canvas = build_canvas(3, 2);
+        write_pixel(&mut canvas, 1, 0, Color::new(1.0, 0.2, 0.4));
+        write_pixel(&mut canvas, 2, 1, Color::new(0.0, 0.6, 1.0));
+        for format in [PpmFormat::P3, PpmFormat::P6].iter() {
+            let read = ppm_to_canvas(&canvas_to_ppm(&canvas, *format)).unwrap();
+            assert_eq!((read.width(), read.height()), (3, 2));
+            for y in 0..2 {
+                for x in 0..3 {
+                    assert_abs_diff_eq!(
+                        pixel_at(&read, x, y),
+                        pixel_at(&canvas, x, y),
+                        epsilon = 0.002
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn comments_and_other_maximum_values_are_read() {
+        let canvas =
+            ppm_to_canvas(b"P3\n# made by hand\n2 1 # size\n100\n100 50 0  0 0 25\n").unwrap();
+        assert_abs_diff_eq!(pixel_at(&canvas, 0, 0), Color::new(1.0, 0.5, 0.0));
+        assert_abs_diff_eq!(pixel_at(&canvas, 1, 0), Color::new(0.0, 0.0, 0.25));
+
+        let wide = [b"P6 1 1 65535\n".as_ref(), &[255, 255, 128, 0, 0, 0]].concat();
+        let canvas = ppm_to_canvas(&wide).unwrap();
+        assert_abs_diff_eq!(
+            pixel_at(&canvas, 0, 0),
+            Color::new(1.0, 0.5, 0.0),
+            epsilon = 0.001
+        );
+    }
+
+    #[test]
+    fn broken_pixmaps_are_rejected() {
+        let message = |ppm: &[u8]| ppm_to_canvas(ppm).unwrap_err().to_string();
+        assert_eq!(message(b"P5 1 1 255 0"), "invalid PPM, unknown format 'P5'");
+        assert_eq!(
+            message(b"P3 2 1 255 0 0 0"),
+            "invalid PPM, the file ends too soon"
+        );
+        assert_eq!(
+            message(b"P3 1 1 255 0 300 0"),
+            "invalid PPM, color value 300 above the maximum 255"
+        );
+        assert_eq!(
+            message(b"P3 x 1 255"),
+            "invalid PPM, expected the width, found 'x'"
+        );
+    }
+
+    #[test]
+    fn sizes_larger_than_the_file_are_rejected_before_allocating() {
+        let message = |ppm: &[u8]| ppm_to_canvas(ppm).unwrap_err().to_string();
+        assert_eq!(
+            message(b"P6 60000 60000 255\n\0\0\0"),
+            "invalid PPM, the file ends too soon"
+        );
+        assert_eq!(
+            message(b"P6 4294967295 4294967295 65535\n"),
+            "invalid PPM, the file ends too soon"
+        );
+        assert_eq!(
+            message(b"P3 1000 1000 255\n0 0 0"),
+            "invalid PPM, the file ends too soon"
+        );
+        // The shortest plain pixmap still fits.
+        assert!(ppm_to_canvas(b"P3 2 1 1\n0 0 0 1 1 1").is_ok());
+    }
+}
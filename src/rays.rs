@@ -5,6 +5,8 @@ use crate::tuples::*;
 pub struct Ray {
     pub origin: Tuple,
     pub direction: Tuple,
+    /// When the ray is cast, from 0 when the shutter opens to 1 when it closes.
+    pub time: f32,
 }
 
 impl Ray {
@@ -15,7 +17,8 @@ impl Ray {
     pub fn transform(self: &Self, transformation: Transformation) -> Ray {
         Ray {
             origin: self.origin.transform(transformation.clone()),
-            direction: self.direction.transform(transformation)
+            direction: self.direction.transform(transformation),
+            time: self.time,
         }
     }
 }
@@ -29,6 +32,7 @@ mod tests {
         let ray = Ray {
             origin: point(2.0, 3.0, 4.0),
             direction: vector(1.0, 0.0, 0.0),
+            time: 0.0,
         };
         assert_abs_diff_eq!(ray.position(0.0), point(2.0, 3.0, 4.0));
     }
@@ -38,6 +42,7 @@ mod tests {
         let ray = Ray {
             origin: point(2.0, 3.0, 4.0),
             direction: vector(1.0, 0.0, 0.0),
+            time: 0.0,
         };
         assert_abs_diff_eq!(ray.position(2.5), point(4.5, 3.0, 4.0));
     }
@@ -47,6 +52,7 @@ mod tests {
         let ray = Ray {
             origin: point(1.0, 2.0, 3.0),
             direction: vector(0.0, 1.0, 0.0),
+            time: 0.0,
         };
         let transformed_ray = ray.transform(translation(3.0, 4.0, 5.0));
         assert_abs_diff_eq!(transformed_ray.origin, point(4.0, 6.0, 8.0));
@@ -58,6 +64,7 @@ mod tests {
         let ray = Ray {
             origin: point(1.0, 2.0, 3.0),
             direction: vector(0.0, 1.0, 0.0),
+            time: 0.0,
         };
         let transformed_ray = ray.transform(scale(2.0, 3.0, 4.0));
         assert_abs_diff_eq!(transformed_ray.origin, point(2.0, 6.0, 12.0));
@@ -0,0 +1,150 @@
+use crate::tuples::*;
+
+use std::f32::consts::PI;
+
+/// Two unit vectors perpendicular to `normal` and to each other.
+pub fn orthonormal_basis(normal: &Tuple) -> (Tuple, Tuple) {
+    let helper = if normal.x().abs() > 0.9 {
+        vector(0.0, 1.0, 0.0)
+    } else {
+        vector(1.0, 0.0, 0.0)
+    };
+    let tangent = cross(&helper, normal).normalize();
+    let bitangent = cross(normal, &tangent);
+    (tangent, bitangent)
+}
+
+/// Expresses a vector given in the local frame of `normal` (where the normal
+/// is the z axis) in world coordinates.
+pub fn to_world(local: &Tuple, normal: &Tuple) -> Tuple {
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    add(
+        &add(&mul(&tangent, local.x()), &mul(&bitangent, local.y())),
+        &mul(normal, local.z()),
+    )
+}
+
+/// Direction in the hemisphere around `normal` with a density proportional to
+/// the cosine with the normal. `u1` and `u2` are uniform numbers in [0, 1).
+pub fn cosine_sample_hemisphere(normal: &Tuple, u1: f32, u2: f32) -> Tuple {
+    let radius = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let local = vector(
+        radius * phi.cos(),
+        radius * phi.sin(),
+        (1.0 - u1).max(0.0).sqrt(),
+    );
+    to_world(&local, normal).normalize()
+}
+
+pub fn cosine_hemisphere_pdf(normal: &Tuple, direction: &Tuple) -> f32 {
+    dot(normal, direction).max(0.0) / PI
+}
+
+/// Point on the unit sphere with uniform density.
+pub fn uniform_sample_sphere(u1: f32, u2: f32) -> Tuple {
+    let z = 1.0 - 2.0 * u1;
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    vector(radius * phi.cos(), radius * phi.sin(), z)
+}
+
+/// Point of the unit disk with uniform density, as `(x, y)`.
+pub fn uniform_sample_disk(u1: f32, u2: f32) -> (f32, f32) {
+    let radius = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    (radius * phi.cos(), radius * phi.sin())
+}
+
+/// Point of the regular polygon with `sides` corners inscribed in the unit
+/// circle with uniform density, as `(x, y)`. The first corner is on the x axis.
+pub fn uniform_sample_polygon(sides: u32, u1: f32, u2: f32) -> (f32, f32) {
+    // Pick one of the triangles joining the center to a side, then a point
+    // inside it.
+    let scaled = u1 * sides as f32;
+    let triangle = (scaled as u32).min(sides - 1);
+    let u1 = scaled - triangle as f32;
+    let (a, b) = if u1 + u2 > 1.0 {
+        (1.0 - u1, 1.0 - u2)
+    } else {
+        (u1, u2)
+    };
+    let angle = 2.0 * PI / sides as f32;
+    let start = angle * triangle as f32;
+    let end = start + angle;
+    (
+        a * start.cos() + b * end.cos(),
+        a * start.sin() + b * end.sin(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sampling::*;
+
+    #[test]
+    fn the_basis_is_orthonormal() {
+        let normal = vector(1.0, 2.0, 3.0).normalize();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        assert_abs_diff_eq!(dot(&tangent, &normal), 0.0, epsilon = 0.0001);
+        assert_abs_diff_eq!(dot(&bitangent, &normal), 0.0, epsilon = 0.0001);
+        assert_abs_diff_eq!(dot(&tangent, &bitangent), 0.0, epsilon = 0.0001);
+        assert_abs_diff_eq!(magnitude(&bitangent), 1.0, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn cosine_samples_stay_in_the_hemisphere() {
+        let normal = vector(0.0, 1.0, 0.0);
+        for i in 0..10 {
+            for j in 0..10 {
+                let direction = cosine_sample_hemisphere(&normal, i as f32 / 10.0, j as f32 / 10.0);
+                assert!(dot(&direction, &normal) >= 0.0);
+                assert_abs_diff_eq!(magnitude(&direction), 1.0, epsilon = 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn the_center_of_the_hemisphere_is_the_normal() {
+        let normal = vector(0.0, 0.0, -1.0);
+        assert_abs_diff_eq!(cosine_sample_hemisphere(&normal, 0.0, 0.0), normal);
+    }
+
+    #[test]
+    fn uniform_sphere_samples_are_unit_vectors() {
+        assert_abs_diff_eq!(uniform_sample_sphere(0.0, 0.3), vector(0.0, 0.0, 1.0));
+        assert_abs_diff_eq!(
+            magnitude(&uniform_sample_sphere(0.3, 0.7)),
+            1.0,
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn disk_samples_stay_in_the_disk() {
+        for i in 0..10 {
+            for j in 0..10 {
+                let (x, y) = uniform_sample_disk(i as f32 / 10.0, j as f32 / 10.0);
+                assert!(x * x + y * y <= 1.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn polygon_samples_stay_in_the_polygon() {
+        // The inscribed circle of a hexagon has a radius of cos(30).
+        let apothem = (PI / 6.0).cos();
+        let mut farthest: f32 = 0.0;
+        for i in 0..60 {
+            for j in 0..10 {
+                let (x, y) = uniform_sample_polygon(6, i as f32 / 60.0, j as f32 / 10.0);
+                let radius = (x * x + y * y).sqrt();
+                farthest = farthest.max(radius);
+                // Along the middle of a side the polygon is at its narrowest.
+                let angle = y.atan2(x).rem_euclid(PI / 3.0) - PI / 6.0;
+                assert!(radius * angle.cos() <= apothem + 0.0001);
+            }
+        }
+        assert!(farthest > 0.8);
+    }
+}
@@ -0,0 +1,592 @@
+//! Scenes described in YAML files, so they can be changed without
+//! recompiling. A scene has a `camera`, a list of `lights` and a list of
+//! `objects`, and optionally `fog` and `light_samples`:
+//!
+//! ```yaml
+//! camera:
+//!   width: 400
+//!   height: 200
+//!   field_of_view: 1.05
+//!   from: [0, 1.5, -5]
+//!   to: [0, 1, 0]
+//!   up: [0, 1, 0]
+//! lights:
+//!   - type: point
+//!     position: [-10, 10, -10]
+//!     intensity: [1, 1, 1]
+//! objects:
+//!   - type: sphere
+//!     transform:
+//!       - [scale, 0.5, 0.5, 0.5]
+//!       - [translation, 1.5, 0.5, -0.5]
+//!     material:
+//!       color: [0.5, 1, 0.1]
+//!       model: { type: microfacet, metallic: 0, roughness: 0.4 }
+//! ```
+//!
+//! Transforms are lists of `translation`, `scale`, `rotate_x`, `rotate_y`,
+//! `rotate_z` and `shearing` steps, applied in the order they are written.
+//! Angles are in radians and colors are `[r, g, b]` lists or a single grey
+//! value. Anything left out gets the same default as in code. Pieces used
+//! more than once can be defined, extended and shared between files, see
+//! `definitions`.
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use yaml_rust::scanner::ScanError;
+
+use crate::bsdfs::ThinFilm;
+use crate::camera::{Camera, Projection};
+use crate::colors::Color;
+use crate::definitions::expand;
+use crate::error::Error;
+use crate::lights::{Attenuation, Light};
+use crate::materials::{BsdfModel, Material};
+use crate::media::Medium;
+use crate::spheres::Sphere;
+use crate::transformations::*;
+use crate::tuples::{point, vector, Tuple};
+use crate::validation::validate;
+use crate::world::World;
+use crate::yaml::{parse, Node, Value};
+
+/// Everything needed to render an image.
+#[derive(Clone, Debug)]
+pub struct Scene {
+    pub camera: Camera,
+    pub world: World,
+}
+
+/// Problem found while reading a scene, with the file and line it was found
+/// on when they are known.
+#[derive(PartialEq, Clone, Debug)]
+pub struct SceneError {
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+}
+
+impl SceneError {
+    pub fn at(node: &Node, message: String) -> SceneError {
+        SceneError {
+            message,
+            file: node.file.as_ref().map(|file| file.to_path_buf()),
+            line: Some(node.line),
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(self: &Self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(formatter, "{}:{}: ", file.display(), line)?,
+            (Some(file), None) => write!(formatter, "{}: ", file.display())?,
+            (None, Some(line)) => write!(formatter, "line {}: ", line)?,
+            (None, None) => {}
+        }
+        write!(formatter, "{}", self.message)
+    }
+}
+
+/// Reads and parses a YAML file, without expanding it.
+pub fn read_file(filename: &Path) -> Result<Node, SceneError> {
+    let source = fs::read_to_string(filename).map_err(|error| SceneError {
+        message: format!("couldn't read the file, {}", error),
+        file: Some(filename.to_path_buf()),
+        line: None,
+    })?;
+    parse_source(&source, Some(filename))
+}
+
+fn parse_source(source: &str, file: Option<&Path>) -> Result<Node, SceneError> {
+    parse(source, file).map_err(|error: ScanError| SceneError {
+        message: format!("invalid YAML, {}", error),
+        file: file.map(Path::to_path_buf),
+        line: Some(error.marker().line()),
+    })
+}
+
+/// Loads a scene file. Its includes are relative to the folder it is in.
+/// Fails with every problem found in the scene.
+pub fn load_scene(filename: &str) -> Result<Scene, Error> {
+    let filename = Path::new(filename);
+    let root = read_file(filename).map_err(|error| Error::Scene(vec![error]))?;
+    let folder = filename.parent().unwrap_or_else(|| Path::new(""));
+    checked_scene(root, folder).map_err(Error::Scene)
+}
+
+/// Reads a scene from YAML. Its includes are relative to the current folder.
+pub fn parse_scene(source: &str) -> Result<Scene, Error> {
+    let root = parse_source(source, None).map_err(|error| Error::Scene(vec![error]))?;
+    checked_scene(root, Path::new("")).map_err(Error::Scene)
+}
+
+fn checked_scene(root: Node, folder: &Path) -> Result<Scene, Vec<SceneError>> {
+    let root = expand(root, folder).map_err(|error| vec![error])?;
+    let problems = validate(&root);
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    to_scene(&root).map_err(|error| vec![error])
+}
+
+fn to_scene(root: &Node) -> Result<Scene, SceneError> {
+    if let Value::Null = root.value {
+        return Err(SceneError::at(root, "the scene is empty".to_string()));
+    }
+    let mut world = World::new();
+    if let Some(node) = optional(root, "lights")? {
+        for light in list(node)? {
+            world.lights.push(to_light(light)?);
+        }
+    }
+    if let Some(node) = optional(root, "objects")? {
+        for object in list(node)? {
+            world.objects.push(to_object(object)?);
+        }
+    }
+    if let Some(node) = optional(root, "fog")? {
+        world.fog = Some(to_medium(node)?);
+    }
+    if let Some(node) = optional(root, "light_samples")? {
+        world.light_samples = count(node)? as usize;
+    }
+    Ok(Scene {
+        camera: to_camera(required(root, "camera")?)?,
+        world,
+    })
+}
+
+fn to_camera(node: &Node) -> Result<Camera, SceneError> {
+    let mut camera = Camera::new(
+        count(required(node, "width")?)?,
+        count(required(node, "height")?)?,
+        number_or(node, "field_of_view", PI / 3.0)?,
+    );
+    let from = point_or(node, "from", point(0.0, 0.0, 0.0))?;
+    let to = point_or(node, "to", point(0.0, 0.0, 1.0))?;
+    let up = vector_or(node, "up", vector(0.0, 1.0, 0.0))?;
+    camera.transformation = look_at(&from, &to, &up);
+    camera.aperture = number_or(node, "aperture", camera.aperture)?;
+    camera.focal_distance = number_or(node, "focal_distance", camera.focal_distance)?;
+    if let Some(blades) = optional(node, "blades")? {
+        camera.blades = count(blades)?;
+    }
+    if let Some(projection) = optional(node, "projection")? {
+        camera.projection = match kind(projection)? {
+            "perspective" => Projection::Perspective,
+            "orthographic" => Projection::Orthographic {
+                view_width: number(required(projection, "view_width")?)?,
+            },
+            "fisheye" => Projection::Fisheye,
+            "equirectangular" => Projection::Equirectangular,
+            "cubemap" => Projection::Cubemap,
+            other => return Err(unknown(projection, "projection", other)),
+        };
+    }
+    Ok(camera)
+}
+
+fn to_light(node: &Node) -> Result<Light, SceneError> {
+    let intensity = color_or(node, "intensity", Color::new(1.0, 1.0, 1.0))?;
+    let light = match kind(node)? {
+        "point" => Light::point(tuple(required(node, "position")?, point)?, intensity),
+        "directional" => {
+            Light::directional(tuple(required(node, "direction")?, vector)?, intensity)
+        }
+        "spot" => Light::spot(
+            tuple(required(node, "position")?, point)?,
+            tuple(required(node, "direction")?, vector)?,
+            intensity,
+            number(required(node, "inner_angle")?)?,
+            number(required(node, "outer_angle")?)?,
+        ),
+        other => return Err(unknown(node, "light", other)),
+    };
+    match optional(node, "attenuation")? {
+        Some(attenuation) => Ok(light.with_attenuation(to_attenuation(attenuation)?)),
+        None => Ok(light),
+    }
+}
+
+fn to_attenuation(node: &Node) -> Result<Attenuation, SceneError> {
+    if let Value::Map(_) = node.value {
+        return Ok(Attenuation::Polynomial {
+            constant: number_or(node, "constant", 0.0)?,
+            linear: number_or(node, "linear", 0.0)?,
+            quadratic: number_or(node, "quadratic", 0.0)?,
+        });
+    }
+    match string(node)? {
+        "none" => Ok(Attenuation::None),
+        "inverse_square" => Ok(Attenuation::InverseSquare),
+        other => Err(unknown(node, "attenuation", other)),
+    }
+}
+
+fn to_object(node: &Node) -> Result<Sphere, SceneError> {
+    let mut sphere = match kind(node)? {
+        "sphere" => Sphere::new(),
+        other => return Err(unknown(node, "shape", other)),
+    };
+    if let Some(transform) = optional(node, "transform")? {
+        sphere.transformation = to_transformation(transform)?;
+    }
+    if let Some(transform) = optional(node, "closing_transform")? {
+        sphere.set_closing_transformation(to_transformation(transform)?);
+    }
+    if let Some(material) = optional(node, "material")? {
+        sphere.material = to_material(material)?;
+    }
+    Ok(sphere)
+}
+
+pub fn to_transformation(node: &Node) -> Result<Transformation, SceneError> {
+    let mut transformation = identity();
+    for step in list(node)? {
+        let items = list(step)?;
+        // Steps coming from a defined transform are nested in a list.
+        if let Some(Value::List(_)) = items.first().map(|item| &item.value) {
+            transformation = to_transformation(step)?.dot(&transformation);
+            continue;
+        }
+        let name = match items.first() {
+            Some(name) => string(name)?,
+            None => return Err(SceneError::at(step, "empty transform step".to_string())),
+        };
+        let arguments = items[1..]
+            .iter()
+            .map(number)
+            .collect::<Result<Vec<f32>, SceneError>>()?;
+        let expected = match name {
+            "translation" | "scale" => 3,
+            "rotate_x" | "rotate_y" | "rotate_z" => 1,
+            "shearing" => 6,
+            other => return Err(unknown(step, "transform", other)),
+        };
+        if arguments.len() != expected {
+            return Err(SceneError::at(
+                step,
+                format!(
+                    "{} takes {} numbers, got {}",
+                    name,
+                    expected,
+                    arguments.len()
+                ),
+            ));
+        }
+        let a = &arguments;
+        let matrix = match name {
+            "translation" => translation(a[0], a[1], a[2]),
+            "scale" => scale(a[0], a[1], a[2]),
+            "rotate_x" => rotate_x(a[0]),
+            "rotate_y" => rotate_y(a[0]),
+            "rotate_z" => rotate_z(a[0]),
+            _ => shearing(a[0], a[1], a[2], a[3], a[4], a[5]),
+        };
+        transformation = matrix.dot(&transformation);
+    }
+    Ok(transformation)
+}
+
+fn to_material(node: &Node) -> Result<Material, SceneError> {
+    let mut material = Material::new();
+    material.color = color_or(node, "color", material.color)?;
+    material.emission = color_or(node, "emission", material.emission)?;
+    material.ambient = number_or(node, "ambient", material.ambient)?;
+    material.diffuse = number_or(node, "diffuse", material.diffuse)?;
+    material.specular = number_or(node, "specular", material.specular)?;
+    material.shininess = number_or(node, "shininess", material.shininess)?;
+    if let Some(model) = optional(node, "model")? {
+        material.model = to_model(model)?;
+    }
+    if let Some(medium) = optional(node, "medium")? {
+        material.medium = Some(to_medium(medium)?);
+    }
+    Ok(material)
+}
+
+/// A model is either its name or a mapping with its `type` and parameters.
+fn to_model(node: &Node) -> Result<BsdfModel, SceneError> {
+    let name = match node.value {
+        Value::Map(_) => kind(node)?,
+        _ => string(node)?,
+    };
+    Ok(match name {
+        "lambertian" => BsdfModel::Lambertian,
+        "phong" => BsdfModel::Phong,
+        "blinn_phong" => BsdfModel::BlinnPhong,
+        "oren_nayar" => BsdfModel::OrenNayar {
+            roughness: number_or(node, "roughness", 0.0)?,
+        },
+        "microfacet" => BsdfModel::Microfacet {
+            metallic: number_or(node, "metallic", 0.0)?,
+            roughness: number_or(node, "roughness", 0.5)?,
+        },
+        "rough_dielectric" => BsdfModel::RoughDielectric {
+            ior: number_or(node, "ior", 1.5)?,
+            roughness: number_or(node, "roughness", 0.0)?,
+        },
+        "clear_coat" => BsdfModel::ClearCoat {
+            base: Box::new(to_model(required(node, "base")?)?),
+            strength: number_or(node, "strength", 1.0)?,
+            ior: number_or(node, "ior", 1.5)?,
+            roughness: number_or(node, "roughness", 0.0)?,
+            thin_film: match optional(node, "thin_film")? {
+                Some(film) => Some(ThinFilm {
+                    thickness: number(required(film, "thickness")?)?,
+                    ior: number_or(film, "ior", 1.33)?,
+                }),
+                None => None,
+            },
+        },
+        "interface" => BsdfModel::Interface,
+        other => return Err(unknown(node, "model", other)),
+    })
+}
+
+fn to_medium(node: &Node) -> Result<Medium, SceneError> {
+    Ok(Medium {
+        absorption: color_or(node, "absorption", Color::black())?,
+        scattering: color_or(node, "scattering", Color::black())?,
+        anisotropy: number_or(node, "anisotropy", 0.0)?,
+    })
+}
+
+fn unknown(node: &Node, what: &str, name: &str) -> SceneError {
+    SceneError::at(node, format!("unknown {} '{}'", what, name))
+}
+
+fn expected(node: &Node, what: &str) -> SceneError {
+    SceneError::at(node, format!("expected {}, found {}", what, node.kind()))
+}
+
+/// Value of `key`, if `node` is a mapping that has it.
+fn optional<'a>(node: &'a Node, key: &str) -> Result<Option<&'a Node>, SceneError> {
+    match node.value {
+        Value::Map(_) => Ok(node.get(key)),
+        _ => Err(expected(node, "a mapping")),
+    }
+}
+
+fn required<'a>(node: &'a Node, key: &str) -> Result<&'a Node, SceneError> {
+    optional(node, key)?.ok_or_else(|| SceneError::at(node, format!("missing '{}'", key)))
+}
+
+/// The `type` of a mapping.
+fn kind(node: &Node) -> Result<&str, SceneError> {
+    string(required(node, "type")?)
+}
+
+fn list(node: &Node) -> Result<&[Node], SceneError> {
+    match &node.value {
+        Value::List(items) => Ok(items),
+        _ => Err(expected(node, "a list")),
+    }
+}
+
+fn string(node: &Node) -> Result<&str, SceneError> {
+    match &node.value {
+        Value::String(text) => Ok(text),
+        _ => Err(expected(node, "a name")),
+    }
+}
+
+fn number(node: &Node) -> Result<f32, SceneError> {
+    match node.value {
+        Value::Number(number) => Ok(number as f32),
+        _ => Err(expected(node, "a number")),
+    }
+}
+
+fn number_or(node: &Node, key: &str, default: f32) -> Result<f32, SceneError> {
+    optional(node, key)?.map_or(Ok(default), number)
+}
+
+/// Whole number that is at least 1.
+fn count(node: &Node) -> Result<u32, SceneError> {
+    match node.value {
+        Value::Number(number) if number >= 1.0 && number.fract() == 0.0 => Ok(number as u32),
+        _ => Err(expected(node, "a positive whole number")),
+    }
+}
+
+fn triple(node: &Node) -> Result<[f32; 3], SceneError> {
+    let items = list(node)?;
+    if items.len() != 3 {
+        return Err(SceneError::at(
+            node,
+            format!("expected 3 numbers, found {}", items.len()),
+        ));
+    }
+    Ok([number(&items[0])?, number(&items[1])?, number(&items[2])?])
+}
+
+fn tuple(node: &Node, build: fn(f32, f32, f32) -> Tuple) -> Result<Tuple, SceneError> {
+    let [x, y, z] = triple(node)?;
+    Ok(build(x, y, z))
+}
+
+fn point_or(node: &Node, key: &str, default: Tuple) -> Result<Tuple, SceneError> {
+    optional(node, key)?.map_or(Ok(default), |node| tuple(node, point))
+}
+
+fn vector_or(node: &Node, key: &str, default: Tuple) -> Result<Tuple, SceneError> {
+    optional(node, key)?.map_or(Ok(default), |node| tuple(node, vector))
+}
+
+fn color_or(node: &Node, key: &str, default: Color) -> Result<Color, SceneError> {
+    match optional(node, key)? {
+        Some(color) => match color.value {
+            Value::Number(grey) => Ok(Color::new(grey as f32, grey as f32, grey as f32)),
+            _ => {
+                let [r, g, b] = triple(color)?;
+                Ok(Color::new(r, g, b))
+            }
+        },
+        None => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scene::*;
+
+    const EXAMPLE: &str = include_str!("../scenes/example.yaml");
+
+    fn first_error(error: Error) -> SceneError {
+        match error {
+            Error::Scene(mut errors) => errors.remove(0),
+            other => panic!("Expected scene errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_example_scene_loads() {
+        let scene = parse_scene(EXAMPLE).unwrap();
+        assert_eq!(scene.camera.width, 400);
+        assert_eq!(scene.world.lights.len(), 1);
+        assert_eq!(scene.world.objects.len(), 4);
+    }
+
+    #[test]
+    fn cameras_look_from_one_point_to_another() {
+        let scene = parse_scene(
+            "camera: { width: 11, height: 11, from: [0, 0, -5], to: [0, 0, 0], up: [0, 1, 0] }",
+        )
+        .unwrap();
+        let ray = scene.camera.ray_for_pixel(5, 5);
+        assert_abs_diff_eq!(ray.origin, point(0.0, 0.0, -5.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(ray.direction, vector(0.0, 0.0, 1.0), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn transforms_are_applied_in_order() {
+        let scene = parse_scene(
+            "
+camera: { width: 1, height: 1 }
+objects:
+  - type: sphere
+    transform:
+      - [scale, 2, 2, 2]
+      - [translation, 1, 0, 0]
+",
+        )
+        .unwrap();
+        assert_abs_diff_eq!(
+            scene.world.objects[0].transformation,
+            translation(1.0, 0.0, 0.0).dot(&scale(2.0, 2.0, 2.0))
+        );
+    }
+
+    #[test]
+    fn materials_and_models_are_read() {
+        let scene = parse_scene(
+            "
+camera: { width: 1, height: 1 }
+objects:
+  - type: sphere
+    material:
+      color: 0.5
+      emission: [1, 0, 0]
+      model:
+        type: clear_coat
+        base: lambertian
+        thin_film: { thickness: 300 }
+",
+        )
+        .unwrap();
+        let material = &scene.world.objects[0].material;
+        assert_eq!(material.color, Color::new(0.5, 0.5, 0.5));
+        assert_eq!(material.emission, Color::new(1.0, 0.0, 0.0));
+        assert_eq!(
+            material.model,
+            BsdfModel::ClearCoat {
+                base: Box::new(BsdfModel::Lambertian),
+                strength: 1.0,
+                ior: 1.5,
+                roughness: 0.0,
+                thin_film: Some(ThinFilm {
+                    thickness: 300.0,
+                    ior: 1.33
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn lights_of_every_type_are_read() {
+        let scene = parse_scene(
+            "
+camera: { width: 1, height: 1 }
+lights:
+  - { type: point, position: [0, 5, 0], attenuation: inverse_square }
+  - { type: directional, direction: [0, -1, 0], intensity: 0.5 }
+  - type: spot
+    position: [0, 5, 0]
+    direction: [0, -1, 0]
+    inner_angle: 0.2
+    outer_angle: 0.4
+",
+        )
+        .unwrap();
+        assert_eq!(
+            scene.world.lights[0],
+            Light::point(point(0.0, 5.0, 0.0), Color::new(1.0, 1.0, 1.0))
+                .with_attenuation(Attenuation::InverseSquare)
+        );
+        assert_eq!(
+            scene.world.lights[1],
+            Light::directional(vector(0.0, -1.0, 0.0), Color::new(0.5, 0.5, 0.5))
+        );
+        assert!(matches!(scene.world.lights[2], Light::Spot(_)));
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let error = parse_scene(
+            "
+camera: { width: 1, height: 1 }
+objects:
+  - type: sphere
+    material:
+      color: [1, 0]
+",
+        )
+        .map_err(first_error)
+        .unwrap_err();
+        assert_eq!(error.line, Some(6));
+        assert_eq!(error.to_string(), "line 6: expected 3 numbers, found 2");
+
+        let error = parse_scene("camera: { width: 1, height: 1 }\nobjects: [{ type: cube }]")
+            .map_err(first_error)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "line 2: unknown shape 'cube'");
+
+        let error = parse_scene("lights: []").map_err(first_error).unwrap_err();
+        assert_eq!(error.message, "missing 'camera'");
+    }
+}
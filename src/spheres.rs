@@ -3,29 +3,87 @@ use crate::rays::Ray;
 use crate::transformations::*;
 use crate::tuples::*;
 use crate::materials::Material;
+use crate::sampling::uniform_sample_sphere;
 
-use ndarray_linalg::solve::Inverse;
+use std::f32::consts::PI;
 
-#[derive(PartialEq, Clone, Debug)]
+/// A point on the surface of a shape together with the surface area it stands for.
+#[derive(Clone, Debug)]
+pub struct SurfaceSample {
+    pub point: Tuple,
+    pub normal: Tuple,
+    pub area: f32,
+}
+
+#[derive(Clone, Debug)]
 pub struct Sphere {
     pub transformation: Transformation,
-    pub material: Material
+    pub material: Material,
+    /// Motion of objects that move while the shutter is open, see
+    /// `set_closing_transformation`. It starts from `transformation` as it
+    /// was when set, and is made again if that has changed since.
+    motion: Option<Motion>,
+}
+
+impl PartialEq for Sphere {
+    fn eq(self: &Self, other: &Sphere) -> bool {
+        self.transformation == other.transformation
+            && self.material == other.material
+            && self.closing_transformation() == other.closing_transformation()
+    }
+}
+
+impl Default for Sphere {
+    fn default() -> Sphere {
+        Sphere::new()
+    }
 }
 
 impl Sphere {
     pub fn new() -> Sphere {
         Sphere {
             transformation: identity(),
-            material: Material::new()
+            material: Material::new(),
+            motion: None,
+        }
+    }
+
+    /// Makes the sphere move while the shutter is open, from `transformation`
+    /// when it opens to `closing` when it closes.
+    pub fn set_closing_transformation(self: &mut Self, closing: Transformation) {
+        self.motion = Some(Motion::new(self.transformation.clone(), closing));
+    }
+
+    /// Transformation when the shutter closes, for moving spheres.
+    pub fn closing_transformation(self: &Self) -> Option<&Transformation> {
+        self.motion.as_ref().map(|motion| motion.end())
+    }
+
+    /// The sphere frozen where it is at `time`, 0 being when the shutter opens
+    /// and 1 when it closes.
+    pub fn at_time(self: &Self, time: f32) -> Sphere {
+        let transformation = match &self.motion {
+            Some(motion) if motion.start() == &self.transformation => motion.at(time),
+            Some(motion) => Motion::new(self.transformation.clone(), motion.end().clone()).at(time),
+            None => return self.clone(),
+        };
+        Sphere {
+            transformation,
+            material: self.material.clone(),
+            motion: None,
         }
     }
 
     pub fn intersect(self: &Self, ray: &Ray) -> Vec<Intersection> {
-        let transformed_ray = ray.transform(
-            self.transformation
-                .inv()
-                .expect("Couldn't invert transformation to apply to ray"),
-        );
+        if self.motion.is_some() {
+            return self.at_time(ray.time).intersect(ray);
+        }
+        // Flattened spheres have nothing inside for rays to hit. Rendering
+        // refuses them beforehand, see `World::check`.
+        let transformed_ray = match inverse(&self.transformation) {
+            Ok(inverse) => ray.transform(inverse),
+            Err(_) => return vec![],
+        };
         log::debug!(
             "Ray: {:?} and inverted: {:?}",
             ray.clone(),
@@ -49,23 +107,116 @@ impl Sphere {
             Intersection {
                 t: t1,
                 object: self.clone(),
+                object_index: None,
             },
             Intersection {
                 t: t2,
                 object: self.clone(),
+                object_index: None,
             },
         ]
     }
 
+    /// Flattened spheres have no normal, the zero vector is returned.
     pub fn normal_at(self: &Self, world_point: &Tuple) -> Tuple {
-        let transformation = self.transformation.inv().expect("Could not invert sphere transform");
-        let object_point = world_point.transform(transformation);
+        let transformation = match inverse(&self.transformation) {
+            Ok(transformation) => transformation,
+            Err(_) => return vector(0.0, 0.0, 0.0),
+        };
+        let object_point = world_point.transform(transformation.clone());
         let object_normal = sub(&object_point, &point(0.0, 0.0, 0.0));
-        let world_transformation = self.transformation.inv().expect("Could not invert sphere transform").t().to_owned();
+        let world_transformation = transformation.t().to_owned();
         let mut world_normal = object_normal.transform(world_transformation);
         world_normal.w = 0.0;
         world_normal
     }
+
+    /// Whether `world_point` is strictly inside the sphere.
+    pub fn contains(self: &Self, world_point: &Tuple) -> bool {
+        let transformation = match inverse(&self.transformation) {
+            Ok(transformation) => transformation,
+            Err(_) => return false,
+        };
+        let object_point = world_point.transform(transformation);
+        magnitude(&sub(&object_point, &point(0.0, 0.0, 0.0))) < 1.0
+    }
+
+    /// Spreads `count` points evenly over the surface using a Fibonacci lattice.
+    /// Areas are in world space so they account for the sphere transformation.
+    pub fn surface_samples(self: &Self, count: usize) -> Vec<SurfaceSample> {
+        let golden_angle = PI * (3.0 - 5.0_f32.sqrt());
+        let object_area = 4.0 * PI / count as f32;
+
+        (0..count)
+            .map(|k| {
+                let z = 1.0 - (2.0 * k as f32 + 1.0) / count as f32;
+                let radius = (1.0 - z * z).sqrt();
+                let phi = golden_angle * k as f32;
+                let object_point = point(radius * phi.cos(), radius * phi.sin(), z);
+                self.surface_sample_at(&object_point, object_area)
+            })
+            .collect()
+    }
+
+    /// Picks a point of the surface with uniform density in object space from
+    /// two uniform numbers in [0, 1). The area of the sample is the inverse of
+    /// its probability density over the world space surface.
+    pub fn sample_surface(self: &Self, u1: f32, u2: f32) -> SurfaceSample {
+        let direction = uniform_sample_sphere(u1, u2);
+        let object_point = point(direction.x(), direction.y(), direction.z());
+        self.surface_sample_at(&object_point, 4.0 * PI)
+    }
+
+    /// Probability density, over the world space surface, of picking
+    /// `world_point` with `sample_surface`.
+    pub fn surface_pdf(self: &Self, world_point: &Tuple) -> f32 {
+        1.0 / (4.0 * PI * self.area_scale(world_point))
+    }
+
+    fn surface_sample_at(self: &Self, object_point: &Tuple, object_area: f32) -> SurfaceSample {
+        let world_point = object_point.transform(self.transformation.clone());
+        SurfaceSample {
+            area: object_area * self.area_scale(&world_point),
+            normal: self.normal_at(&world_point).normalize(),
+            point: world_point,
+        }
+    }
+
+    /// Opposite corners of the smallest box aligned with the axes holding the
+    /// sphere. Moving spheres get a box holding both keyframes.
+    pub fn bounds(self: &Self) -> (Tuple, Tuple) {
+        let (low, high) = keyframe_bounds(&self.transformation);
+        match self.closing_transformation() {
+            Some(closing) => {
+                let (closing_low, closing_high) = keyframe_bounds(closing);
+                (min(&low, &closing_low), max(&high, &closing_high))
+            }
+            None => (low, high),
+        }
+    }
+
+    /// How much a small patch of the surface around `world_point` grows when
+    /// going from object space to world space.
+    fn area_scale(self: &Self, world_point: &Tuple) -> f32 {
+        let volume_scale = determinant(&self.transformation).abs();
+        volume_scale * magnitude(&self.normal_at(world_point))
+    }
+}
+
+/// Bounds of the unit sphere moved by `transformation`. Along each axis it
+/// reaches as far as the length of that row of the linear part.
+fn keyframe_bounds(transformation: &Transformation) -> (Tuple, Tuple) {
+    let extent = |row: usize| {
+        (0..3)
+            .map(|column| transformation[[row, column]].powi(2))
+            .sum::<f32>()
+            .sqrt()
+    };
+    let center = |row: usize| transformation[[row, 3]];
+    (
+        point(center(0) - extent(0), center(1) - extent(1), center(2) - extent(2)),
+        point(center(0) + extent(0), center(1) + extent(1), center(2) + extent(2)),
+    )
 }
 
 #[cfg(test)]
@@ -83,6 +234,7 @@ mod tests {
         let ray = Ray {
             origin: point(0.0, 0.0, -5.0),
             direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
         };
         let sphere = Sphere::new();
         let intersections = sphere.intersect(&ray);
@@ -98,6 +250,7 @@ mod tests {
         let ray = Ray {
             origin: point(0.0, 1.0, -5.0),
             direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
         };
         let sphere = Sphere::new();
         let intersections = sphere.intersect(&ray);
@@ -115,6 +268,7 @@ mod tests {
         let ray = Ray {
             origin: point(0.0, 2.0, -5.0),
             direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
         };
         let sphere = Sphere::new();
         let intersections = sphere.intersect(&ray);
@@ -126,6 +280,7 @@ mod tests {
         let ray = Ray {
             origin: point(0.0, 0.0, 0.0),
             direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
         };
         let sphere = Sphere::new();
         let intersections = sphere.intersect(&ray);
@@ -143,6 +298,7 @@ mod tests {
         let ray = Ray {
             origin: point(0.0, 0.0, 5.0),
             direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
         };
         let sphere = Sphere::new();
         let intersections = sphere.intersect(&ray);
@@ -159,6 +315,7 @@ mod tests {
         let ray = Ray {
             origin: point(0.0, 0.0, -5.0),
             direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
         };
         let mut sphere = Sphere::new();
         sphere.transformation = scale(2.0, 2.0, 2.0);
@@ -221,4 +378,110 @@ mod tests {
             vector(0.0, 0.70711, -0.70711)
         )
     }
+
+    #[test]
+    fn surface_samples_lie_on_the_sphere() {
+        let mut sphere = Sphere::new();
+        sphere.transformation = translation(1.0, 2.0, 3.0);
+        for sample in sphere.surface_samples(32) {
+            let from_center = sub(&sample.point, &point(1.0, 2.0, 3.0));
+            assert_abs_diff_eq!(magnitude(&from_center), 1.0, epsilon = 0.0001);
+            assert_abs_diff_eq!(sample.normal, from_center, epsilon = 0.0001);
+        }
+    }
+
+    #[test]
+    fn surface_samples_cover_the_area_of_a_scaled_sphere() {
+        let mut sphere = Sphere::new();
+        sphere.transformation = scale(2.0, 2.0, 2.0);
+        let area: f32 = sphere.surface_samples(64).iter().map(|s| s.area).sum();
+        assert_abs_diff_eq!(area, 16.0 * PI, epsilon = 0.001);
+    }
+
+    #[test]
+    fn a_random_surface_sample_of_a_scaled_sphere() {
+        let mut sphere = Sphere::new();
+        sphere.transformation = scale(3.0, 3.0, 3.0);
+        let sample = sphere.sample_surface(0.25, 0.5);
+        assert_abs_diff_eq!(magnitude(&sub(&sample.point, &point(0.0, 0.0, 0.0))), 3.0, epsilon = 0.0001);
+        assert_abs_diff_eq!(sample.area, 36.0 * PI, epsilon = 0.001);
+    }
+
+    #[test]
+    fn the_surface_pdf_is_the_inverse_of_the_sample_area() {
+        let mut sphere = Sphere::new();
+        sphere.transformation = scale(1.0, 2.0, 3.0);
+        let sample = sphere.sample_surface(0.3, 0.6);
+        assert_abs_diff_eq!(
+            sphere.surface_pdf(&sample.point) * sample.area,
+            1.0,
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn a_sphere_contains_the_points_inside_it() {
+        let mut sphere = Sphere::new();
+        sphere.transformation = translation(0.0, 0.0, 3.0).dot(&scale(2.0, 2.0, 2.0));
+        assert!(sphere.contains(&point(0.0, 1.5, 3.0)));
+        assert!(!sphere.contains(&point(0.0, 2.5, 3.0)));
+        assert!(!sphere.contains(&point(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn a_moving_sphere_is_hit_where_it_is_when_the_ray_is_cast() {
+        let mut sphere = Sphere::new();
+        sphere.set_closing_transformation(translation(4.0, 0.0, 0.0));
+        let ray_at = |time: f32| Ray {
+            origin: point(2.0, 0.0, -5.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time,
+        };
+        assert!(sphere.intersect(&ray_at(0.0)).is_empty());
+        let intersections = sphere.intersect(&ray_at(0.5));
+        assert_eq!(intersections.len(), 2);
+        assert_abs_diff_eq!(intersections[0].t, 4.0, epsilon = 0.0001);
+        // Normals are computed where the sphere was hit.
+        let normal = intersections[0].object.normal_at(&point(2.0, 0.0, -1.0));
+        assert_abs_diff_eq!(normal.normalize(), vector(0.0, 0.0, -1.0), epsilon = 0.0001);
+        assert!(sphere.intersect(&ray_at(1.0)).is_empty());
+    }
+
+    #[test]
+    fn moving_spheres_start_from_their_current_transformation() {
+        let mut sphere = Sphere::new();
+        sphere.set_closing_transformation(translation(4.0, 0.0, 0.0));
+        sphere.transformation = translation(2.0, 0.0, 0.0);
+        assert_abs_diff_eq!(
+            sphere.at_time(0.0).transformation,
+            translation(2.0, 0.0, 0.0),
+            epsilon = 0.0001
+        );
+        assert_abs_diff_eq!(
+            sphere.at_time(0.5).transformation,
+            translation(3.0, 0.0, 0.0),
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn bounds_hold_the_transformed_sphere() {
+        let mut sphere = Sphere::new();
+        sphere.transformation = translation(1.0, 2.0, 3.0).dot(&scale(2.0, 1.0, 1.0));
+        let (low, high) = sphere.bounds();
+        assert_abs_diff_eq!(low, point(-1.0, 1.0, 2.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(high, point(3.0, 3.0, 4.0), epsilon = 0.0001);
+
+        // Turned a quarter around z, the long side goes along y.
+        sphere.transformation = rotate_z(PI / 2.0).dot(&scale(2.0, 1.0, 1.0));
+        let (low, high) = sphere.bounds();
+        assert_abs_diff_eq!(low, point(-1.0, -2.0, -1.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(high, point(1.0, 2.0, 1.0), epsilon = 0.0001);
+
+        sphere.transformation = identity();
+        sphere.set_closing_transformation(translation(5.0, 0.0, 0.0));
+        let (low, high) = sphere.bounds();
+        assert_abs_diff_eq!(low, point(-1.0, -1.0, -1.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(high, point(6.0, 1.0, 1.0), epsilon = 0.0001);
+    }
 }
@@ -0,0 +1,159 @@
+use crate::camera::Camera;
+use crate::canvas::*;
+use crate::error::Error;
+
+/// Where each eye goes on a stereo canvas.
+#[derive(PartialEq, Clone, Debug)]
+pub enum StereoLayout {
+    /// Left eye on the left half, right eye on the right one.
+    SideBySide,
+    /// Left eye on the top half, right eye on the bottom one.
+    OverUnder,
+}
+
+/// Two eyes `interocular_distance` apart, on both sides of `camera`, whose
+/// views cross at `convergence_distance`. Each eye gets an image the size of
+/// the camera's. With the equirectangular projection this renders
+/// omni-directional stereo panoramas.
+#[derive(Clone, Debug)]
+pub struct StereoRig {
+    pub camera: Camera,
+    pub interocular_distance: f32,
+    pub convergence_distance: f32,
+    pub layout: StereoLayout,
+}
+
+impl StereoRig {
+    /// Side-by-side rig with parallel eyes.
+    pub fn new(camera: Camera, interocular_distance: f32) -> StereoRig {
+        StereoRig {
+            camera,
+            interocular_distance,
+            convergence_distance: f32::INFINITY,
+            layout: StereoLayout::SideBySide,
+        }
+    }
+
+    pub fn left_eye(self: &Self) -> Camera {
+        self.eye(-1.0)
+    }
+
+    pub fn right_eye(self: &Self) -> Camera {
+        self.eye(1.0)
+    }
+
+    fn eye(self: &Self, side: f32) -> Camera {
+        let mut camera = self.camera.clone();
+        camera.eye_offset = side * self.interocular_distance / 2.0;
+        camera.convergence_distance = self.convergence_distance;
+        camera
+    }
+
+    /// Size of the canvas holding both eyes.
+    pub fn canvas_size(self: &Self) -> (u32, u32) {
+        match self.layout {
+            StereoLayout::SideBySide => (self.camera.width * 2, self.camera.height),
+            StereoLayout::OverUnder => (self.camera.width, self.camera.height * 2),
+        }
+    }
+
+    /// Renders each eye with `render_eye`, for instance `camera::render` with
+    /// a world or `PathTracer::render`, and lays both images out on one canvas.
+    pub fn render<F>(self: &Self, render_eye: F) -> Result<Canvas, Error>
+    where
+        F: Fn(&Camera) -> Result<Canvas, Error>,
+    {
+        let (width, height) = self.canvas_size();
+        let mut canvas = build_canvas(width, height);
+        paste(&mut canvas, &render_eye(&self.left_eye())?, 0, 0);
+        let right = render_eye(&self.right_eye())?;
+        match self.layout {
+            StereoLayout::SideBySide => paste(&mut canvas, &right, self.camera.width, 0),
+            StereoLayout::OverUnder => paste(&mut canvas, &right, 0, self.camera.height),
+        }
+        Ok(canvas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Projection;
+    use crate::colors::Color;
+    use crate::stereo::*;
+    use crate::tuples::*;
+
+    use std::f32::consts::PI;
+
+    #[test]
+    fn eyes_sit_on_both_sides_of_the_camera() {
+        let rig = StereoRig::new(Camera::new(101, 101, PI / 2.0), 0.064);
+        let left = rig.left_eye().ray_for_pixel(50, 50);
+        let right = rig.right_eye().ray_for_pixel(50, 50);
+        assert_abs_diff_eq!(left.origin, point(-0.032, 0.0, 0.0), epsilon = 0.0001);
+        assert_abs_diff_eq!(right.origin, point(0.032, 0.0, 0.0), epsilon = 0.0001);
+        // Parallel eyes look straight ahead.
+        assert_abs_diff_eq!(left.direction, right.direction, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn converging_eyes_meet_at_the_convergence_distance() {
+        let mut rig = StereoRig::new(Camera::new(101, 101, PI / 2.0), 0.5);
+        rig.convergence_distance = 4.0;
+        let left = rig.left_eye().ray_for_pixel(50, 50);
+        let right = rig.right_eye().ray_for_pixel(50, 50);
+        let meeting = point(0.0, 0.0, 4.0);
+        assert_abs_diff_eq!(
+            left.position(4.0 / left.direction.z()),
+            meeting,
+            epsilon = 0.0001
+        );
+        assert_abs_diff_eq!(
+            right.position(4.0 / right.direction.z()),
+            meeting,
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn panoramic_eyes_turn_with_the_view() {
+        let mut camera = Camera::new(200, 100, PI / 2.0);
+        camera.projection = Projection::Equirectangular;
+        let rig = StereoRig::new(camera, 0.5);
+        // Looking along +x, the left eye is towards +z.
+        let ray = rig.left_eye().ray_for_pixel_offset(150, 50, 0.0, 0.0);
+        assert_abs_diff_eq!(ray.origin, point(0.0, 0.0, 0.25), epsilon = 0.0001);
+        assert_abs_diff_eq!(ray.direction, vector(1.0, 0.0, 0.0), epsilon = 0.0001);
+        let ray = rig.left_eye().ray_for_pixel_offset(100, 50, 0.0, 0.0);
+        assert_abs_diff_eq!(ray.origin, point(-0.25, 0.0, 0.0), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn eyes_are_laid_out_on_one_canvas() {
+        let mut rig = StereoRig::new(Camera::new(3, 2, PI / 2.0), 0.1);
+        // Paints the left eye red and the right one blue.
+        let render_eye = |camera: &Camera| {
+            let mut canvas = build_canvas(camera.width, camera.height);
+            let color = if camera.eye_offset < 0.0 {
+                Color::new(1.0, 0.0, 0.0)
+            } else {
+                Color::new(0.0, 0.0, 1.0)
+            };
+            for y in 0..camera.height {
+                for x in 0..camera.width {
+                    write_pixel(&mut canvas, x, y, color.clone());
+                }
+            }
+            Ok(canvas)
+        };
+        assert_eq!(rig.canvas_size(), (6, 2));
+        let canvas = rig.render(render_eye).unwrap();
+        assert_eq!(pixel_at(&canvas, 2, 1), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(pixel_at(&canvas, 3, 0), Color::new(0.0, 0.0, 1.0));
+
+        rig.layout = StereoLayout::OverUnder;
+        assert_eq!(rig.canvas_size(), (3, 4));
+        let canvas = rig.render(render_eye).unwrap();
+        assert_eq!(pixel_at(&canvas, 2, 1), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(pixel_at(&canvas, 0, 2), Color::new(0.0, 0.0, 1.0));
+    }
+}
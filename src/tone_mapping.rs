@@ -0,0 +1,187 @@
+//! Post-process bringing the radiance of a canvas into the 0..1 range of 8
+//! bit images. Exposure scales the image first, then a tone mapper rolls off
+//! the highlights instead of clipping them.
+
+use std::str::FromStr;
+
+use crate::canvas::*;
+use crate::colors::Color;
+
+/// Curve applied to each channel. All of them map 0 to 0 and never go
+/// above 1.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ToneMapper {
+    /// Cuts everything above 1, the same as no tone mapping.
+    Clamp,
+    /// x / (1 + x), which never reaches white.
+    Reinhard,
+    /// Reinhard reaching white at the radiance `white`.
+    ExtendedReinhard { white: f32 },
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+}
+
+impl ToneMapper {
+    pub fn map(self: &Self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        let mapped = match *self {
+            ToneMapper::Clamp => x,
+            ToneMapper::Reinhard => x / (1.0 + x),
+            ToneMapper::ExtendedReinhard { white } => x * (1.0 + x / (white * white)) / (1.0 + x),
+            ToneMapper::Aces => x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14),
+            // The curve is made brighter and scaled to be white at 11.2.
+            ToneMapper::Hable => hable(2.0 * x) / hable(11.2),
+        };
+        mapped.min(1.0)
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+/// Names for the command line. The white point of extended Reinhard is 4
+/// unless given after a colon, as in `extended-reinhard:8`.
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ToneMapper, String> {
+        let mut parts = name.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("clamp"), None) => Ok(ToneMapper::Clamp),
+            (Some("reinhard"), None) => Ok(ToneMapper::Reinhard),
+            (Some("extended-reinhard"), white) => {
+                let white = match white {
+                    Some(white) => white
+                        .parse()
+                        .ok()
+                        .filter(|white: &f32| *white > 0.0)
+                        .ok_or_else(|| format!("invalid white point '{}'", white))?,
+                    None => 4.0,
+                };
+                Ok(ToneMapper::ExtendedReinhard { white })
+            }
+            (Some("aces"), None) => Ok(ToneMapper::Aces),
+            (Some("hable"), None) => Ok(ToneMapper::Hable),
+            _ => Err(format!(
+                "unknown tone mapper '{}', expected clamp, reinhard, \
+                 extended-reinhard, aces or hable",
+                name
+            )),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct ToneMapping {
+    /// Stops of exposure compensation, each doubling the radiance.
+    pub exposure: f32,
+    pub mapper: ToneMapper,
+}
+
+impl ToneMapping {
+    pub fn new(mapper: ToneMapper) -> ToneMapping {
+        ToneMapping {
+            exposure: 0.0,
+            mapper,
+        }
+    }
+
+    pub fn map_color(self: &Self, color: &Color) -> Color {
+        let scale = 2.0_f32.powf(self.exposure);
+        Color::new(
+            self.mapper.map(color.r * scale),
+            self.mapper.map(color.g * scale),
+            self.mapper.map(color.b * scale),
+        )
+    }
+
+    pub fn apply(self: &Self, canvas: &Canvas) -> Canvas {
+        let mut mapped = build_canvas(canvas.width(), canvas.height());
+        for y in 0..canvas.height() {
+            for x in 0..canvas.width() {
+                write_pixel(&mut mapped, x, y, self.map_color(&pixel_at(canvas, x, y)));
+            }
+        }
+        mapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tone_mapping::*;
+
+    const MAPPERS: [ToneMapper; 5] = [
+        ToneMapper::Clamp,
+        ToneMapper::Reinhard,
+        ToneMapper::ExtendedReinhard { white: 4.0 },
+        ToneMapper::Aces,
+        ToneMapper::Hable,
+    ];
+
+    #[test]
+    fn every_mapper_keeps_black_and_rises_up_to_one() {
+        for mapper in MAPPERS.iter() {
+            assert_abs_diff_eq!(mapper.map(0.0), 0.0, epsilon = 0.0001);
+            assert_abs_diff_eq!(mapper.map(-1.0), 0.0, epsilon = 0.0001);
+            let mut previous = 0.0;
+            for step in 1..200 {
+                let value = mapper.map(step as f32 * 0.1);
+                assert!(value >= previous, "{:?} goes down at {}", mapper, step);
+                assert!(value <= 1.0);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn highlights_roll_off_instead_of_clipping() {
+        assert_eq!(ToneMapper::Clamp.map(2.0), 1.0);
+        assert_abs_diff_eq!(ToneMapper::Reinhard.map(1.0), 0.5);
+        assert!(ToneMapper::Reinhard.map(2.0) < ToneMapper::Reinhard.map(4.0));
+        let extended = ToneMapper::ExtendedReinhard { white: 4.0 };
+        assert_abs_diff_eq!(extended.map(4.0), 1.0);
+        assert!(extended.map(3.0) < 1.0);
+        assert_abs_diff_eq!(ToneMapper::Hable.map(11.2 / 2.0), 1.0, epsilon = 0.0001);
+        assert!(ToneMapper::Aces.map(2.0) < 1.0);
+    }
+
+    #[test]
+    fn each_stop_of_exposure_doubles_the_radiance() {
+        let mut tone_mapping = ToneMapping::new(ToneMapper::Clamp);
+        tone_mapping.exposure = 1.0;
+        let color = Color::new(0.1, 0.2, 0.7);
+        assert_abs_diff_eq!(
+            tone_mapping.map_color(&color),
+            Color::new(0.2, 0.4, 1.0),
+            epsilon = 0.0001
+        );
+        tone_mapping.exposure = -2.0;
+        let mut canvas = build_canvas(2, 1);
+        write_pixel(&mut canvas, 1, 0, Color::new(4.0, 2.0, 1.0));
+        let mapped = tone_mapping.apply(&canvas);
+        assert_abs_diff_eq!(
+            pixel_at(&mapped, 1, 0),
+            Color::new(1.0, 0.5, 0.25),
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn mappers_are_named_on_the_command_line() {
+        assert_eq!("aces".parse(), Ok(ToneMapper::Aces));
+        assert_eq!(
+            "extended-reinhard".parse(),
+            Ok(ToneMapper::ExtendedReinhard { white: 4.0 })
+        );
+        assert_eq!(
+            "extended-reinhard:8".parse(),
+            Ok(ToneMapper::ExtendedReinhard { white: 8.0 })
+        );
+        assert!("extended-reinhard:0".parse::<ToneMapper>().is_err());
+        assert!("filmic".parse::<ToneMapper>().is_err());
+    }
+}
@@ -1,12 +1,11 @@
-use crate::tuples::point;
+use crate::error::Error;
+use crate::tuples::{cross, sub, Tuple};
 
 use ndarray::prelude::*;
 use ndarray::Array2;
 
 use ndarray_linalg::solve::Inverse;
 
-use std::f32::consts::PI;
-
 pub type Transformation = Array2<f32>;
 
 pub fn identity() -> Transformation {
@@ -63,7 +62,7 @@ pub fn rotate_z(r: f32) -> Transformation {
     ]
 }
 
-fn shearing(xy: f32, xz: f32, yx: f32, yz: f32, zx: f32, zy: f32) -> Transformation {
+pub fn shearing(xy: f32, xz: f32, yx: f32, yz: f32, zx: f32, zy: f32) -> Transformation {
     array![
         [1.0, xy, xz, 0.0],
         [yx, 1.0, yz, 0.0],
@@ -72,9 +71,288 @@ fn shearing(xy: f32, xz: f32, yx: f32, yz: f32, zx: f32, zy: f32) -> Transformat
     ]
 }
 
+/// Determinant of the linear part of an affine transformation, how much it
+/// scales volumes.
+pub fn determinant(m: &Transformation) -> f32 {
+    m[[0, 0]] * (m[[1, 1]] * m[[2, 2]] - m[[1, 2]] * m[[2, 1]])
+        - m[[0, 1]] * (m[[1, 0]] * m[[2, 2]] - m[[1, 2]] * m[[2, 0]])
+        + m[[0, 2]] * (m[[1, 0]] * m[[2, 1]] - m[[1, 1]] * m[[2, 0]])
+}
+
+/// Whether `transformation` flattens space, so it can't be undone. The
+/// determinant is compared to the product of the lengths of the axes it
+/// transforms to, which it can't exceed, so small objects aren't flattened.
+pub fn is_singular(transformation: &Transformation) -> bool {
+    let axes: f32 = (0..3)
+        .map(|column| {
+            (0..3)
+                .map(|row| transformation[[row, column]].powi(2))
+                .sum::<f32>()
+                .sqrt()
+        })
+        .product();
+    let determinant = determinant(transformation);
+    !determinant.is_finite() || determinant.abs() <= 1e-6 * axes
+}
+
+/// Transformation undoing `transformation`. Those flattening space, like a
+/// scale by 0, can't be undone.
+pub fn inverse(transformation: &Transformation) -> Result<Transformation, Error> {
+    if is_singular(transformation) {
+        return Err(Error::SingularTransformation { object: None });
+    }
+    transformation
+        .inv()
+        .map_err(|_| Error::SingularTransformation { object: None })
+}
+
+/// Camera transformation placing it at `from` and looking at `to`, with
+/// `up` roughly pointing up in the image.
+pub fn look_at(from: &Tuple, to: &Tuple, up: &Tuple) -> Transformation {
+    let forward = sub(to, from).normalize();
+    let right = cross(&up.normalize(), &forward).normalize();
+    let true_up = cross(&forward, &right);
+    array![
+        [right.x(), true_up.x(), forward.x(), from.x()],
+        [right.y(), true_up.y(), forward.y(), from.y()],
+        [right.z(), true_up.z(), forward.z(), from.z()],
+        [0.0, 0.0, 0.0, 1.0]
+    ]
+}
+
+/// Transformation between `start`, at time 0, and `end`, at time 1. See
+/// `Motion`, which decomposes the keyframes once for many times.
+pub fn interpolate(start: &Transformation, end: &Transformation, time: f32) -> Transformation {
+    Motion::new(start.clone(), end.clone()).at(time)
+}
+
+/// Translation, rotation and scale along the axes, in the order they are
+/// applied to points: scale first.
+type Decomposition = ([f32; 3], Quaternion, [f32; 3]);
+
+/// Motion of an object between two keyframes, the transformation at time 0
+/// and the one at time 1. Keyframes are decomposed when the motion is made.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Motion {
+    start: Transformation,
+    end: Transformation,
+    decomposed: Option<(Decomposition, Decomposition)>,
+}
+
+impl Motion {
+    pub fn new(start: Transformation, end: Transformation) -> Motion {
+        let decomposed = decompose(&start).zip(decompose(&end));
+        Motion {
+            start,
+            end,
+            decomposed,
+        }
+    }
+
+    pub fn start(self: &Self) -> &Transformation {
+        &self.start
+    }
+
+    pub fn end(self: &Self) -> &Transformation {
+        &self.end
+    }
+
+    /// Transformation at `time`. Translation, rotation and scale are
+    /// interpolated separately so turning objects keep their shape, and
+    /// rotations take the shortest way, so keyframes must be less than half a
+    /// turn apart. Keyframes that aren't made of these, with shearing or
+    /// scaled after being rotated, are interpolated element by element.
+    pub fn at(self: &Self, time: f32) -> Transformation {
+        let lerp = |a: f32, b: f32| a + (b - a) * time;
+        match &self.decomposed {
+            Some((start, end)) => compose(&(
+                [
+                    lerp(start.0[0], end.0[0]),
+                    lerp(start.0[1], end.0[1]),
+                    lerp(start.0[2], end.0[2]),
+                ],
+                slerp(&start.1, &end.1, time),
+                [
+                    lerp(start.2[0], end.2[0]),
+                    lerp(start.2[1], end.2[1]),
+                    lerp(start.2[2], end.2[2]),
+                ],
+            )),
+            None => {
+                let mut result = identity();
+                for row in 0..3 {
+                    for column in 0..4 {
+                        result[[row, column]] =
+                            lerp(self.start[[row, column]], self.end[[row, column]]);
+                    }
+                }
+                result
+            }
+        }
+    }
+}
+
+/// Unit quaternion as (w, x, y, z).
+type Quaternion = [f32; 4];
+
+/// Splits a transformation into translation, rotation and scale, or nothing
+/// when these don't make it up again.
+fn decompose(transformation: &Transformation) -> Option<Decomposition> {
+    let translation = [
+        transformation[[0, 3]],
+        transformation[[1, 3]],
+        transformation[[2, 3]],
+    ];
+    let mut scale = [0.0; 3];
+    let mut rotation = [[0.0; 3]; 3];
+    for column in 0..3 {
+        scale[column] = (0..3)
+            .map(|row| transformation[[row, column]].powi(2))
+            .sum::<f32>()
+            .sqrt();
+        if scale[column] == 0.0 {
+            return None;
+        }
+        for row in 0..3 {
+            rotation[row][column] = transformation[[row, column]] / scale[column];
+        }
+    }
+    let determinant = rotation[0][0]
+        * (rotation[1][1] * rotation[2][2] - rotation[1][2] * rotation[2][1])
+        - rotation[0][1] * (rotation[1][0] * rotation[2][2] - rotation[1][2] * rotation[2][0])
+        + rotation[0][2] * (rotation[1][0] * rotation[2][1] - rotation[1][1] * rotation[2][0]);
+    // Mirror images are a rotation with a negative scale.
+    if determinant < 0.0 {
+        scale[0] = -scale[0];
+        for row in rotation.iter_mut() {
+            row[0] = -row[0];
+        }
+    }
+    let decomposition = (translation, quaternion_from_matrix(&rotation), scale);
+    // Columns that aren't orthogonal, as after a rotation then a scale, give
+    // a rotation that is no rotation at all.
+    let tolerance = 1e-4
+        * transformation
+            .iter()
+            .fold(1.0, |max: f32, x| max.max(x.abs()));
+    let rebuilt = compose(&decomposition);
+    if rebuilt
+        .iter()
+        .zip(transformation.iter())
+        .all(|(a, b)| (a - b).abs() <= tolerance)
+    {
+        Some(decomposition)
+    } else {
+        None
+    }
+}
+
+fn compose((translation, rotation, scale): &Decomposition) -> Transformation {
+    let rotation = rotation_matrix(rotation);
+    let mut result = identity();
+    for row in 0..3 {
+        for column in 0..3 {
+            result[[row, column]] = rotation[row][column] * scale[column];
+        }
+        result[[row, 3]] = translation[row];
+    }
+    result
+}
+
+fn quaternion_from_matrix(m: &[[f32; 3]; 3]) -> Quaternion {
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        [
+            s / 4.0,
+            (m[2][1] - m[1][2]) / s,
+            (m[0][2] - m[2][0]) / s,
+            (m[1][0] - m[0][1]) / s,
+        ]
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+        [
+            (m[2][1] - m[1][2]) / s,
+            s / 4.0,
+            (m[0][1] + m[1][0]) / s,
+            (m[0][2] + m[2][0]) / s,
+        ]
+    } else if m[1][1] > m[2][2] {
+        let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+        [
+            (m[0][2] - m[2][0]) / s,
+            (m[0][1] + m[1][0]) / s,
+            s / 4.0,
+            (m[1][2] + m[2][1]) / s,
+        ]
+    } else {
+        let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+        [
+            (m[1][0] - m[0][1]) / s,
+            (m[0][2] + m[2][0]) / s,
+            (m[1][2] + m[2][1]) / s,
+            s / 4.0,
+        ]
+    }
+}
+
+fn rotation_matrix(q: &Quaternion) -> [[f32; 3]; 3] {
+    let [w, x, y, z] = *q;
+    [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - w * z),
+            2.0 * (x * z + w * y),
+        ],
+        [
+            2.0 * (x * y + w * z),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - w * x),
+        ],
+        [
+            2.0 * (x * z - w * y),
+            2.0 * (y * z + w * x),
+            1.0 - 2.0 * (x * x + y * y),
+        ],
+    ]
+}
+
+/// Spherical interpolation between two rotations, the shortest way.
+fn slerp(start: &Quaternion, end: &Quaternion, time: f32) -> Quaternion {
+    let mut cosine: f32 = (0..4).map(|i| start[i] * end[i]).sum();
+    let mut end = *end;
+    if cosine < 0.0 {
+        cosine = -cosine;
+        end = [-end[0], -end[1], -end[2], -end[3]];
+    }
+    let (start_weight, end_weight) = if cosine > 0.9995 {
+        (1.0 - time, time)
+    } else {
+        let angle = cosine.acos();
+        (
+            ((1.0 - time) * angle).sin() / angle.sin(),
+            (time * angle).sin() / angle.sin(),
+        )
+    };
+    let mut result = [0.0; 4];
+    for i in 0..4 {
+        result[i] = start[i] * start_weight + end[i] * end_weight;
+    }
+    let norm = result.iter().map(|c| c * c).sum::<f32>().sqrt();
+    [
+        result[0] / norm,
+        result[1] / norm,
+        result[2] / norm,
+        result[3] / norm,
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use crate::transformations::*;
+    use crate::tuples::{point, vector};
+
+    use std::f32::consts::PI;
 
     #[test]
     fn identity_does_not_nodify_a_point() {
@@ -156,6 +434,50 @@ mod tests {
         assert_abs_diff_eq!(transformed_point.z(), 0.0);
     }
 
+    #[test]
+    fn looking_at_a_point() {
+        let from = point(1.0, 2.0, 3.0);
+        let camera = look_at(&from, &point(1.0, 2.0, 10.0), &vector(0.0, 1.0, 0.0));
+        assert_abs_diff_eq!(camera, translation(1.0, 2.0, 3.0), epsilon = 0.0001);
+        let camera = look_at(&from, &point(5.0, 2.0, 3.0), &vector(0.0, 3.0, 0.0));
+        assert_abs_diff_eq!(
+            vector(0.0, 0.0, 1.0).transform(camera.clone()),
+            vector(1.0, 0.0, 0.0),
+            epsilon = 0.0001
+        );
+        assert_abs_diff_eq!(
+            vector(1.0, 0.0, 0.0).transform(camera),
+            vector(0.0, 0.0, -1.0),
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn flattening_transformations_cannot_be_undone() {
+        let inverted = inverse(&translation(1.0, 2.0, 3.0)).unwrap();
+        assert_abs_diff_eq!(
+            point(1.0, 2.0, 3.0).transform(inverted),
+            point(0.0, 0.0, 0.0)
+        );
+        assert!(inverse(&scale(1.0, 0.0, 1.0)).is_err());
+    }
+
+    #[test]
+    fn small_objects_can_be_undone() {
+        let small = scale(0.0009, 0.0009, 0.0009);
+        assert!(!is_singular(&small));
+        let inverted = inverse(&small).unwrap();
+        assert_abs_diff_eq!(
+            point(0.0009, 0.0, 0.0).transform(inverted),
+            point(1.0, 0.0, 0.0),
+            epsilon = 0.0001
+        );
+        assert!(is_singular(&scale(0.0009, 0.0, 0.0009)));
+        assert!(is_singular(
+            &shearing(1.0, 0.0, 0.0, 0.0, 0.0, 0.0).dot(&scale(1.0, 1.0, 0.0))
+        ));
+    }
+
     #[test]
     fn shearing_x_in_proportion_to_z() {
         let shearing = shearing(0.0, 1.0, 0.0, 0.0, 0.0, 0.0);
@@ -175,4 +497,57 @@ mod tests {
         assert_abs_diff_eq!(transformed_point.y(), 5.0);
         assert_abs_diff_eq!(transformed_point.z(), 4.0);
     }
+
+    #[test]
+    fn interpolating_the_same_transformation_keeps_it() {
+        let transformation = translation(1.0, 2.0, 3.0)
+            .dot(&rotate_y(0.7))
+            .dot(&scale(2.0, 1.0, 0.5));
+        let interpolated = interpolate(&transformation, &transformation, 0.3);
+        for (a, b) in interpolated.iter().zip(transformation.iter()) {
+            assert_abs_diff_eq!(a, b, epsilon = 0.0001);
+        }
+    }
+
+    #[test]
+    fn interpolating_translations_moves_in_a_straight_line() {
+        let start = translation(0.0, 0.0, 0.0);
+        let end = translation(4.0, -2.0, 0.0);
+        let point = point(0.0, 0.0, 0.0).transform(interpolate(&start, &end, 0.25));
+        assert_abs_diff_eq!(point.x(), 1.0, epsilon = 0.0001);
+        assert_abs_diff_eq!(point.y(), -0.5, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn interpolating_rotations_turns_without_shrinking() {
+        let start = rotate_z(0.0);
+        let end = rotate_z(PI / 2.0);
+        let point = point(1.0, 0.0, 0.0).transform(interpolate(&start, &end, 0.5));
+        let half = 2.0_f32.sqrt() / 2.0;
+        assert_abs_diff_eq!(point.x(), half, epsilon = 0.0001);
+        assert_abs_diff_eq!(point.y(), half, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn keyframes_scaled_after_a_rotation_are_kept() {
+        let start = scale(2.0, 1.0, 1.0).dot(&rotate_z(0.5));
+        let end = translation(1.0, 0.0, 0.0).dot(&start);
+        let motion = Motion::new(start.clone(), end.clone());
+        for (time, keyframe) in [(0.0, &start), (1.0, &end)].iter() {
+            for (a, b) in motion.at(*time).iter().zip(keyframe.iter()) {
+                assert_abs_diff_eq!(a, b, epsilon = 0.0001);
+            }
+        }
+        let point = point(0.0, 0.0, 0.0).transform(motion.at(0.5));
+        assert_abs_diff_eq!(point.x(), 0.5, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn sheared_keyframes_keep_their_shearing() {
+        let start = shearing(1.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        let interpolated = interpolate(&start, &start, 0.5);
+        for (a, b) in interpolated.iter().zip(start.iter()) {
+            assert_abs_diff_eq!(a, b, epsilon = 0.0001);
+        }
+    }
 }
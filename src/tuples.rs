@@ -139,6 +139,26 @@ pub fn cross(a: &Tuple, b: &Tuple) -> Tuple {
     )
 }
 
+/// Smallest of each component.
+pub fn min(a: &Tuple, b: &Tuple) -> Tuple {
+    Tuple {
+        x: a.x.min(b.x),
+        y: a.y.min(b.y),
+        z: a.z.min(b.z),
+        w: a.w.min(b.w),
+    }
+}
+
+/// Largest of each component.
+pub fn max(a: &Tuple, b: &Tuple) -> Tuple {
+    Tuple {
+        x: a.x.max(b.x),
+        y: a.y.max(b.y),
+        z: a.z.max(b.z),
+        w: a.w.max(b.w),
+    }
+}
+
 pub fn reflect(v: &Tuple, n: &Tuple) -> Tuple {
     let a = dot(&v, &n);
     sub(v, &mul(&mul(n, a), 2.0))
@@ -309,4 +329,12 @@ mod tests {
         let normal = vector(2.0_f32.sqrt() / 2.0, 2.0_f32.sqrt() / 2.0, 0.0);
         assert_abs_diff_eq!(reflect(&vector_to_reflect, &normal), vector(1.0, 0.0, 0.0));
     }
+
+    #[test]
+    fn min_and_max_go_component_by_component() {
+        let a = point(1.0, -2.0, 3.0);
+        let b = point(-1.0, 2.0, 3.5);
+        assert_abs_diff_eq!(min(&a, &b), point(-1.0, -2.0, 3.0));
+        assert_abs_diff_eq!(max(&a, &b), point(1.0, 2.0, 3.5));
+    }
 }
@@ -0,0 +1,367 @@
+//! Checks of a scene file that go beyond reading it, so that mistakes are
+//! reported with their location before rendering starts instead of panicking
+//! halfway through it or silently giving a wrong image.
+
+use crate::scene::{to_transformation, SceneError};
+use crate::transformations::is_singular;
+use crate::yaml::{Node, Value};
+
+/// Every problem found in `root`, an expanded scene, sorted by file and line.
+/// Parts that can't be read at all are left for the loader to report.
+pub fn validate(root: &Node) -> Vec<SceneError> {
+    let mut validator = Validator { problems: vec![] };
+    validator.numbers(root);
+    if let Some(camera) = root.get("camera") {
+        validator.camera(camera);
+    }
+    for light in items(root.get("lights")) {
+        validator.colors(light, &["intensity"]);
+        validator.not_negative(light, &["inner_angle", "outer_angle"]);
+        if let Some(attenuation) = light.get("attenuation") {
+            validator.not_negative(attenuation, &["constant", "linear", "quadratic"]);
+        }
+    }
+    for object in items(root.get("objects")) {
+        for key in ["transform", "closing_transform"].iter() {
+            if let Some(transform) = object.get(key) {
+                validator.transform(transform);
+            }
+        }
+        if let Some(material) = object.get("material") {
+            validator.material(material);
+        }
+    }
+    if let Some(fog) = root.get("fog") {
+        validator.medium(fog);
+    }
+    let mut problems = validator.problems;
+    problems.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    problems
+}
+
+struct Validator {
+    problems: Vec<SceneError>,
+}
+
+impl Validator {
+    fn problem(self: &mut Self, node: &Node, message: String) {
+        self.problems.push(SceneError::at(node, message));
+    }
+
+    /// Numbers that aren't, like `.nan` and `.inf`, anywhere in the scene.
+    fn numbers(self: &mut Self, node: &Node) {
+        match &node.value {
+            Value::Number(number) if !number.is_finite() => {
+                self.problem(node, format!("{} is not a usable number", number))
+            }
+            Value::List(items) => items.iter().for_each(|item| self.numbers(item)),
+            Value::Map(entries) => entries.iter().for_each(|(_, value)| self.numbers(value)),
+            _ => {}
+        }
+    }
+
+    fn camera(self: &mut Self, camera: &Node) {
+        self.between(camera, &["field_of_view"], 0.0, 2.0 * std::f64::consts::PI);
+        self.not_negative(camera, &["aperture", "focal_distance"]);
+        let from = camera
+            .get("from")
+            .and_then(triple)
+            .unwrap_or([0.0, 0.0, 0.0]);
+        let to = camera.get("to").and_then(triple).unwrap_or([0.0, 0.0, 1.0]);
+        let forward = [to[0] - from[0], to[1] - from[1], to[2] - from[2]];
+        if length(forward) < 1e-6 {
+            self.problem(
+                camera.get("to").unwrap_or(camera),
+                "the camera looks at the point it is at".to_string(),
+            );
+        }
+        if let Some(node) = camera.get("up") {
+            if let Some(up) = triple(node) {
+                if length(up) < 1e-6 {
+                    self.problem(
+                        node,
+                        "the up vector of the camera has no length".to_string(),
+                    );
+                } else if length(cross(up, forward)) < 1e-6 * length(up) * length(forward) {
+                    self.problem(
+                        node,
+                        "the up vector of the camera is along its view direction".to_string(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Transforms flattening objects can't be undone to intersect them.
+    fn transform(self: &mut Self, node: &Node) {
+        if let Value::List(steps) = &node.value {
+            for step in steps {
+                if let Value::String(name) = &step.value {
+                    self.problem(step, format!("unknown transform '{}'", name));
+                }
+            }
+        }
+        if let Ok(transformation) = to_transformation(node) {
+            if is_singular(&transformation) {
+                self.problem(
+                    node,
+                    "the transform flattens the object, it can't be undone".to_string(),
+                );
+            }
+        }
+    }
+
+    fn material(self: &mut Self, material: &Node) {
+        if let Value::String(name) = &material.value {
+            self.problem(material, format!("unknown material '{}'", name));
+            return;
+        }
+        self.colors(material, &["color", "emission"]);
+        self.not_negative(material, &["ambient", "diffuse", "specular", "shininess"]);
+        if let Some(model) = material.get("model") {
+            self.model(model);
+        }
+        if let Some(medium) = material.get("medium") {
+            self.medium(medium);
+        }
+    }
+
+    fn model(self: &mut Self, model: &Node) {
+        self.between(model, &["roughness", "metallic", "strength"], 0.0, 1.0);
+        self.positive(model, &["ior"]);
+        if let Some(base) = model.get("base") {
+            self.model(base);
+        }
+        if let Some(film) = model.get("thin_film") {
+            self.not_negative(film, &["thickness"]);
+            self.positive(film, &["ior"]);
+        }
+    }
+
+    fn medium(self: &mut Self, medium: &Node) {
+        if let Value::String(name) = &medium.value {
+            self.problem(medium, format!("unknown medium '{}'", name));
+            return;
+        }
+        self.colors(medium, &["absorption", "scattering"]);
+        for node in values(medium, &["anisotropy"]) {
+            if let Value::Number(g) = node.value {
+                if g <= -1.0 || g >= 1.0 {
+                    self.problem(node, "'anisotropy' must be between -1 and 1".to_string());
+                }
+            }
+        }
+    }
+
+    /// Colors, either lists or single grey values, can't be negative.
+    fn colors(self: &mut Self, node: &Node, keys: &[&str]) {
+        for (key, color) in keys.iter().zip(values(node, keys)) {
+            let channels = match &color.value {
+                Value::List(channels) => channels.iter().collect(),
+                _ => vec![color],
+            };
+            for channel in channels {
+                if let Value::Number(value) = channel.value {
+                    if value < 0.0 {
+                        self.problem(channel, format!("'{}' can't be negative", key));
+                    }
+                }
+            }
+        }
+    }
+
+    fn not_negative(self: &mut Self, node: &Node, keys: &[&str]) {
+        for (key, value) in keys.iter().zip(values(node, keys)) {
+            if let Value::Number(number) = value.value {
+                if number < 0.0 {
+                    self.problem(value, format!("'{}' can't be negative", key));
+                }
+            }
+        }
+    }
+
+    fn positive(self: &mut Self, node: &Node, keys: &[&str]) {
+        for (key, value) in keys.iter().zip(values(node, keys)) {
+            if let Value::Number(number) = value.value {
+                if number <= 0.0 {
+                    self.problem(value, format!("'{}' must be positive", key));
+                }
+            }
+        }
+    }
+
+    fn between(self: &mut Self, node: &Node, keys: &[&str], minimum: f64, maximum: f64) {
+        for (key, value) in keys.iter().zip(values(node, keys)) {
+            if let Value::Number(number) = value.value {
+                if number < minimum || number > maximum {
+                    self.problem(
+                        value,
+                        format!("'{}' must be between {} and {:.2}", key, minimum, maximum),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Values of `keys` in `node`, with a placeholder for the missing ones so
+/// they stay next to their key when zipped.
+fn values<'a>(node: &'a Node, keys: &[&str]) -> Vec<&'a Node> {
+    keys.iter()
+        .map(|key| node.get(key).unwrap_or(&MISSING))
+        .collect()
+}
+
+const MISSING: Node = Node {
+    value: Value::Null,
+    line: 0,
+    file: None,
+};
+
+fn items(node: Option<&Node>) -> &[Node] {
+    match node.map(|node| &node.value) {
+        Some(Value::List(items)) => items,
+        _ => &[],
+    }
+}
+
+fn triple(node: &Node) -> Option<[f64; 3]> {
+    match &node.value {
+        Value::List(items) if items.len() == 3 => {
+            let mut numbers = [0.0; 3];
+            for (number, item) in numbers.iter_mut().zip(items) {
+                match item.value {
+                    Value::Number(value) => *number = value,
+                    _ => return None,
+                }
+            }
+            Some(numbers)
+        }
+        _ => None,
+    }
+}
+
+fn length(v: [f64; 3]) -> f64 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::scene::parse_scene;
+    use crate::validation::*;
+    use crate::yaml::parse;
+
+    fn problems(source: &str) -> Vec<String> {
+        validate(&parse(source, None).unwrap())
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn a_good_scene_has_no_problems() {
+        let scene = include_str!("../scenes/example.yaml");
+        assert!(validate(&parse(scene, None).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn flattened_objects_are_reported() {
+        let found = problems(
+            "
+objects:
+  - type: sphere
+    transform: [[scale, 1, 0, 1]]
+  - type: sphere
+    transform: [[rotate_x, 1], [shearing, 1, 0, 0, 0, 0, 0]]
+  - type: sphere
+    transform: [[scale, 0.0009, 0.0009, 0.0009]]
+",
+        );
+        assert_eq!(
+            found,
+            vec!["line 4: the transform flattens the object, it can't be undone"]
+        );
+    }
+
+    #[test]
+    fn negative_and_missing_numbers_are_reported() {
+        let found = problems(
+            "
+objects:
+  - type: sphere
+    material:
+      color: [1, -0.5, 0]
+      diffuse: .nan
+      model: { type: microfacet, roughness: 2 }
+",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "line 5: 'color' can't be negative",
+                "line 6: NaN is not a usable number",
+                "line 7: 'roughness' must be between 0 and 1.00",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_references_are_reported() {
+        let found = problems(
+            "
+objects:
+  - type: sphere
+    transform: [[scale, 2, 2, 2], lift]
+    material: glas
+",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "line 4: unknown transform 'lift'",
+                "line 5: unknown material 'glas'",
+            ]
+        );
+    }
+
+    #[test]
+    fn cameras_need_a_sensible_up_vector() {
+        let found = problems("camera: { width: 1, height: 1, up: [0, 0, 0] }");
+        assert_eq!(
+            found,
+            vec!["line 1: the up vector of the camera has no length"]
+        );
+        let found = problems("camera:\n  to: [0, 0, 5]\n  up: [0, 0, 1]");
+        assert_eq!(
+            found,
+            vec!["line 3: the up vector of the camera is along its view direction"]
+        );
+    }
+
+    #[test]
+    fn loading_a_scene_reports_every_problem() {
+        let errors = match parse_scene(
+            "
+camera: { width: 1, height: 1 }
+objects:
+  - { type: sphere, material: { ambient: -1 } }
+  - { type: sphere, transform: [[scale, 0, 0, 0]] }
+",
+        ) {
+            Err(Error::Scene(errors)) => errors,
+            other => panic!("Expected scene errors, got {:?}", other),
+        };
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].line, Some(4));
+        assert_eq!(errors[1].line, Some(5));
+    }
+}
@@ -0,0 +1,510 @@
+use std::cmp::Ordering;
+
+use crate::colors::*;
+use crate::error::Error;
+use crate::intersections::{hit, Intersection};
+use crate::lights::Light;
+use crate::materials::{lighting, BsdfModel};
+use crate::media::Medium;
+use crate::rays::Ray;
+use crate::spheres::Sphere;
+use crate::transformations::inverse;
+use crate::tuples::{dot, magnitude, max, min, sub, Tuple};
+
+/// Distance used to move points off surfaces so they don't intersect themselves.
+pub const EPSILON: f32 = 0.001;
+
+#[derive(Clone, Debug)]
+pub struct World {
+    pub objects: Vec<Sphere>,
+    pub lights: Vec<Light>,
+    /// Number of points sampled on every emissive object to light the scene with it.
+    pub light_samples: usize,
+    /// Medium filling the space outside of the objects.
+    pub fog: Option<Medium>,
+}
+
+impl Default for World {
+    fn default() -> World {
+        World::new()
+    }
+}
+
+impl World {
+    pub fn new() -> World {
+        World {
+            objects: vec![],
+            lights: vec![],
+            light_samples: 16,
+            fog: None,
+        }
+    }
+
+    /// Point lights standing for the emissive objects of the world as seen from
+    /// `point_at`. Each one carries the light emitted by a patch of the surface,
+    /// so closer and more frontal patches shine brighter. Moving objects are
+    /// sampled where they are at `time`, and the object at index
+    /// `shaded_object`, if any, doesn't light itself.
+    pub fn geometry_lights(
+        self: &Self,
+        point_at: &Tuple,
+        time: f32,
+        shaded_object: Option<usize>,
+    ) -> Vec<Light> {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(|(index, object)| {
+                object.material.emission != Color::black() && Some(*index) != shaded_object
+            })
+            .flat_map(|(_, object)| {
+                object
+                    .at_time(time)
+                    .surface_samples(self.light_samples)
+                    .into_iter()
+                    .filter_map(move |sample| {
+                        let to_point = sub(point_at, &sample.point);
+                        let distance_squared = dot(&to_point, &to_point);
+                        let cos_light = dot(&sample.normal, &to_point.normalize());
+                        if cos_light <= 0.0 || distance_squared <= 0.0 {
+                            return None;
+                        }
+                        let weight = sample.area * cos_light / distance_squared;
+                        Some(Light::point(
+                            sample.point,
+                            multiply(&object.material.emission, weight),
+                        ))
+                    })
+            })
+            .collect()
+    }
+
+    /// Opposite corners of the smallest box aligned with the axes holding
+    /// every object, or nothing when the world is empty.
+    pub fn bounds(self: &Self) -> Option<(Tuple, Tuple)> {
+        self.objects
+            .iter()
+            .map(|object| object.bounds())
+            .fold(None, |bounds, (low, high)| match bounds {
+                None => Some((low, high)),
+                Some((bounds_low, bounds_high)) => {
+                    Some((min(&low, &bounds_low), max(&high, &bounds_high)))
+                }
+            })
+    }
+
+    /// Fails when an object can't be rendered, because one of its
+    /// transformations flattens it.
+    pub fn check(self: &Self) -> Result<(), Error> {
+        for (index, object) in self.objects.iter().enumerate() {
+            let keyframes = Some(&object.transformation).into_iter();
+            for transformation in keyframes.chain(object.closing_transformation()) {
+                inverse(transformation).map_err(|_| Error::SingularTransformation {
+                    object: Some(index),
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// All the intersections of the ray with the objects of the world, sorted by `t`.
+    pub fn intersect(self: &Self, ray: &Ray) -> Vec<Intersection> {
+        let mut intersections: Vec<Intersection> = self
+            .objects
+            .iter()
+            .enumerate()
+            .flat_map(|(index, object)| {
+                object
+                    .intersect(ray)
+                    .into_iter()
+                    .map(move |intersection| Intersection {
+                        object_index: Some(index),
+                        ..intersection
+                    })
+            })
+            .collect();
+        intersections.sort_by(|x, y| x.t.partial_cmp(&y.t).unwrap_or(Ordering::Equal));
+        intersections
+    }
+
+    /// Color of the intersection, adding up the light emitted by the object and
+    /// the contribution of every light, including emissive objects.
+    pub fn shade_hit(self: &Self, intersection: &Intersection, ray: &Ray) -> Color {
+        let point = ray.position(intersection.t);
+        let normal = intersection.object.normal_at(&point);
+        let eye = ray.direction.negate();
+        let emission = intersection.object.material.emission.clone();
+        let geometry_lights = self.geometry_lights(&point, ray.time, intersection.object_index);
+
+        self.lights
+            .iter()
+            .chain(geometry_lights.iter())
+            .fold(emission, |color, light| {
+                crate::colors::add(
+                    color,
+                    lighting(
+                        intersection.object.material.clone(),
+                        light.clone(),
+                        &point,
+                        &eye,
+                        &normal,
+                    ),
+                )
+            })
+    }
+
+    /// Whether something blocks the way from the origin of `ray` before
+    /// reaching `distance`. Directions must be normalized.
+    pub fn is_shadowed(self: &Self, ray: &Ray, distance: f32) -> bool {
+        let ray = Ray {
+            origin: ray.position(EPSILON),
+            direction: ray.direction.clone(),
+            time: ray.time,
+        };
+        match hit(self.intersect(&ray)) {
+            Some(intersection) => intersection.t < distance - 2.0 * EPSILON,
+            None => false,
+        }
+    }
+
+    /// Medium at `point_at` and `time`: the one of the last added object
+    /// holding it, so volumes nested in others must be added after them, or
+    /// else the fog.
+    pub fn medium_at(self: &Self, point_at: &Tuple, time: f32) -> Option<&Medium> {
+        self.objects
+            .iter()
+            .rev()
+            .filter(|object| object.at_time(time).contains(point_at))
+            .find_map(|object| object.material.medium.as_ref())
+            .or(self.fog.as_ref())
+    }
+
+    /// Fraction of the light that makes it from the origin of `ray` to
+    /// `distance`, going through media and interfaces. Any other surface
+    /// blocks the light. Directions must be normalized.
+    pub fn transmittance(self: &Self, ray: &Ray, distance: f32) -> Color {
+        let mut ray = Ray {
+            origin: ray.position(EPSILON),
+            direction: ray.direction.clone(),
+            time: ray.time,
+        };
+        let mut remaining = distance - 2.0 * EPSILON;
+        let mut transmittance = Color::new(1.0, 1.0, 1.0);
+        loop {
+            let next = hit(self.intersect(&ray)).filter(|intersection| intersection.t < remaining);
+            let segment = next
+                .as_ref()
+                .map_or(remaining, |intersection| intersection.t);
+            if let Some(medium) = self.medium_at(&ray.position(segment / 2.0), ray.time) {
+                transmittance = hadamard_product(&transmittance, &medium.transmittance(segment));
+            }
+            match next {
+                None => return transmittance,
+                Some(intersection)
+                    if intersection.object.material.model == BsdfModel::Interface =>
+                {
+                    ray.origin = ray.position(intersection.t + EPSILON);
+                    remaining -= intersection.t + EPSILON;
+                }
+                Some(_) => return Color::black(),
+            }
+        }
+    }
+
+    /// Color seen along the ray. Media only dim what is behind them here; the
+    /// path tracer also accounts for the light they scatter.
+    pub fn color_at(self: &Self, ray: &Ray) -> Color {
+        let intersection = match hit(self.intersect(ray)) {
+            Some(intersection) => intersection,
+            None => return Color::black(),
+        };
+        let color = if intersection.object.material.model == BsdfModel::Interface {
+            self.color_at(&Ray {
+                origin: ray.position(intersection.t + EPSILON),
+                direction: ray.direction.clone(),
+                time: ray.time,
+            })
+        } else {
+            self.shade_hit(&intersection, ray)
+        };
+        match self.medium_at(&ray.position(intersection.t / 2.0), ray.time) {
+            Some(medium) => {
+                let distance = intersection.t * magnitude(&ray.direction);
+                hadamard_product(&color, &medium.transmittance(distance))
+            }
+            None => color,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::Material;
+    use crate::media::Medium;
+    use crate::transformations::*;
+    use crate::tuples::{point, vector};
+    use crate::world::*;
+
+    fn default_world() -> World {
+        let mut world = World::new();
+        world.objects.push(Sphere::new());
+        world
+    }
+
+    #[test]
+    fn intersections_with_the_world_are_sorted() {
+        let mut world = default_world();
+        let mut far_sphere = Sphere::new();
+        far_sphere.transformation = translation(0.0, 0.0, 5.0);
+        world.objects.insert(0, far_sphere);
+        let ray = Ray {
+            origin: point(0.0, 0.0, -5.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let intersections = world.intersect(&ray);
+        let ts: Vec<f32> = intersections.iter().map(|i| i.t).collect();
+        assert_eq!(ts, vec![4.0, 6.0, 9.0, 11.0]);
+    }
+
+    #[test]
+    fn a_ray_that_misses_is_black() {
+        let mut world = default_world();
+        world.lights.push(Light::point(
+            point(-10.0, 10.0, -10.0),
+            Color::new(1.0, 1.0, 1.0),
+        ));
+        let ray = Ray {
+            origin: point(0.0, 0.0, -5.0),
+            direction: vector(0.0, 1.0, 0.0),
+            time: 0.0,
+        };
+        assert_abs_diff_eq!(world.color_at(&ray), Color::black());
+    }
+
+    #[test]
+    fn the_contribution_of_several_lights_is_added() {
+        let ray = Ray {
+            origin: point(0.0, 0.0, -5.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let light = Light::point(point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+
+        let mut one_light = default_world();
+        one_light.lights.push(light.clone());
+        let mut two_lights = default_world();
+        two_lights.lights.push(light.clone());
+        two_lights.lights.push(light);
+
+        let single = one_light.color_at(&ray);
+        assert_abs_diff_eq!(single, Color::new(1.9, 1.9, 1.9), epsilon = 0.0001);
+        assert_abs_diff_eq!(
+            two_lights.color_at(&ray),
+            multiply(&single, 2.0),
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    fn a_world_without_lights_is_black() {
+        let mut world = default_world();
+        world.objects[0].material = Material::new();
+        let ray = Ray {
+            origin: point(0.0, 0.0, -5.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        assert_abs_diff_eq!(world.color_at(&ray), Color::black());
+    }
+
+    #[test]
+    fn an_emissive_object_glows_without_lights() {
+        let mut world = default_world();
+        world.objects[0].material.emission = Color::new(0.5, 0.2, 0.1);
+        let ray = Ray {
+            origin: point(0.0, 0.0, -5.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        assert_abs_diff_eq!(world.color_at(&ray), Color::new(0.5, 0.2, 0.1));
+    }
+
+    #[test]
+    fn an_emissive_object_lights_its_neighbours() {
+        let mut world = default_world();
+        let mut lamp = Sphere::new();
+        lamp.transformation = translation(0.0, 0.0, -3.0);
+        lamp.material.emission = Color::new(1.0, 1.0, 1.0);
+        world.objects.push(lamp);
+        let ray = Ray {
+            origin: point(0.0, 5.0, -0.5),
+            direction: vector(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let color = world.color_at(&ray);
+        assert!(color.r > 0.0);
+        assert_abs_diff_eq!(color.r, color.b);
+    }
+
+    #[test]
+    fn geometry_lights_only_come_from_the_side_facing_the_point() {
+        let mut world = World::new();
+        let mut lamp = Sphere::new();
+        lamp.material.emission = Color::new(1.0, 1.0, 1.0);
+        world.objects.push(lamp);
+        let lights = world.geometry_lights(&point(0.0, 0.0, -5.0), 0.0, None);
+        assert!(!lights.is_empty());
+        for light in lights {
+            assert!(light.direction_from(&point(0.0, 0.0, 0.0)).z() < 0.0);
+        }
+    }
+
+    #[test]
+    fn an_emissive_object_does_not_light_itself() {
+        let mut world = World::new();
+        let mut lamp = Sphere::new();
+        lamp.material.emission = Color::new(1.0, 1.0, 1.0);
+        world.objects.push(lamp.clone());
+        assert!(world
+            .geometry_lights(&point(0.0, 0.0, -1.0), 0.0, Some(0))
+            .is_empty());
+        // Another lamp just like it, in the same place, still lights what
+        // the first one sees.
+        world.objects.push(lamp);
+        let seen_from = point(0.0, 0.0, -5.0);
+        assert_eq!(
+            world.geometry_lights(&seen_from, 0.0, Some(0)).len(),
+            world.geometry_lights(&seen_from, 0.0, None).len() / 2
+        );
+    }
+
+    #[test]
+    fn moving_emissive_objects_light_from_where_they_are() {
+        let mut world = World::new();
+        let mut lamp = Sphere::new();
+        lamp.material.emission = Color::new(1.0, 1.0, 1.0);
+        lamp.set_closing_transformation(translation(0.0, 0.0, 10.0));
+        world.objects.push(lamp);
+        let seen_from = point(0.0, 0.0, 5.0);
+        let towards = |time: f32| {
+            world.geometry_lights(&seen_from, time, None)[0]
+                .direction_from(&seen_from)
+                .z()
+        };
+        assert!(towards(0.0) < 0.0);
+        assert!(towards(1.0) > 0.0);
+    }
+
+    #[test]
+    fn a_point_is_shadowed_by_an_object_in_between() {
+        let world = default_world();
+        let ray_from = |origin: Tuple| Ray {
+            origin,
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        assert!(world.is_shadowed(&ray_from(point(0.0, 0.0, -5.0)), 10.0));
+        assert!(!world.is_shadowed(&ray_from(point(0.0, 0.0, -5.0)), 3.0));
+        assert!(!world.is_shadowed(&ray_from(point(0.0, 2.0, -5.0)), 10.0));
+    }
+
+    fn ray_through_the_origin() -> Ray {
+        Ray {
+            origin: point(0.0, 0.0, -5.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        }
+    }
+
+    fn smoke_ball() -> Sphere {
+        let mut sphere = Sphere::new();
+        sphere.material.model = BsdfModel::Interface;
+        sphere.material.medium = Some(Medium {
+            absorption: Color::new(0.0, 1.0, 1.0),
+            scattering: Color::black(),
+            anisotropy: 0.0,
+        });
+        sphere
+    }
+
+    #[test]
+    fn the_medium_of_a_point_is_the_one_of_the_volume_holding_it() {
+        let mut world = World::new();
+        world.fog = Some(Medium::fog(0.1));
+        world.objects.push(smoke_ball());
+        assert_eq!(
+            world.medium_at(&point(0.0, 0.0, 0.0), 0.0),
+            smoke_ball().material.medium.as_ref()
+        );
+        assert_eq!(
+            world.medium_at(&point(0.0, 0.0, -5.0), 0.0),
+            Some(&Medium::fog(0.1))
+        );
+    }
+
+    #[test]
+    fn light_crossing_a_volume_is_tinted_by_it() {
+        let mut world = World::new();
+        world.objects.push(smoke_ball());
+        let transmittance = world.transmittance(&ray_through_the_origin(), 10.0);
+        let expected = (-2.0_f32).exp();
+        assert_abs_diff_eq!(
+            transmittance,
+            Color::new(1.0, expected, expected),
+            epsilon = 0.001
+        );
+    }
+
+    #[test]
+    fn opaque_objects_block_all_the_light() {
+        let world = default_world();
+        let transmittance = world.transmittance(&ray_through_the_origin(), 10.0);
+        assert_abs_diff_eq!(transmittance, Color::black());
+    }
+
+    #[test]
+    fn fog_dims_the_objects_behind_it() {
+        let mut world = default_world();
+        world.objects[0].material.emission = Color::new(1.0, 1.0, 1.0);
+        let ray = Ray {
+            origin: point(0.0, 0.0, -5.0),
+            direction: vector(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let clear = world.color_at(&ray);
+        world.fog = Some(Medium::fog(0.25));
+        let expected = multiply(&clear, (-1.0_f32).exp());
+        assert_abs_diff_eq!(world.color_at(&ray), expected, epsilon = 0.0001);
+    }
+
+    #[test]
+    fn bounds_hold_every_object() {
+        let mut world = World::new();
+        assert!(world.bounds().is_none());
+        world.objects.push(Sphere::new());
+        let mut far = Sphere::new();
+        far.transformation = translation(10.0, 0.0, 0.0);
+        world.objects.push(far);
+        let (low, high) = world.bounds().unwrap();
+        assert_abs_diff_eq!(low, point(-1.0, -1.0, -1.0));
+        assert_abs_diff_eq!(high, point(11.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn flattened_objects_are_found_before_rendering() {
+        let mut world = World::new();
+        world.objects.push(Sphere::new());
+        assert!(world.check().is_ok());
+        let mut flat = Sphere::new();
+        flat.set_closing_transformation(scale(1.0, 0.0, 1.0));
+        world.objects.push(flat);
+        match world.check() {
+            Err(Error::SingularTransformation { object }) => assert_eq!(object, Some(1)),
+            other => panic!("Expected a singular transformation, got {:?}", other),
+        }
+        let mut flat = Sphere::new();
+        flat.transformation = scale(1.0, 0.0, 1.0);
+        assert!(flat.intersect(&ray_through_the_origin()).is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::{Marker, ScanError, TScalarStyle};
+use yaml_rust::Yaml;
+
+/// YAML value along with the file and line it starts on, so that whatever
+/// reads it can point at the offending line. Lines start at 1.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Node {
+    pub value: Value,
+    pub line: usize,
+    pub file: Option<Rc<PathBuf>>,
+}
+
+/// Mappings keep their keys in the order they were written.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    List(Vec<Node>),
+    Map(Vec<(String, Node)>),
+}
+
+impl Node {
+    /// Value of `key` when this is a mapping that has it.
+    pub fn get(self: &Self, key: &str) -> Option<&Node> {
+        match &self.value {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, node)| node),
+            _ => None,
+        }
+    }
+
+    /// Human name of the kind of value, for error messages.
+    pub fn kind(self: &Self) -> &'static str {
+        match self.value {
+            Value::Null => "nothing",
+            Value::Boolean(_) => "a boolean",
+            Value::Number(_) => "a number",
+            Value::String(_) => "a string",
+            Value::List(_) => "a list",
+            Value::Map(_) => "a mapping",
+        }
+    }
+}
+
+/// Parses the first document of `source`, read from `file` if it comes from
+/// one. An empty source gives a null node. Anchors and aliases are resolved;
+/// mapping keys must be plain scalars.
+pub fn parse(source: &str, file: Option<&Path>) -> Result<Node, ScanError> {
+    let mut builder = Builder {
+        file: file.map(|file| Rc::new(file.to_path_buf())),
+        stack: vec![],
+        keys: vec![],
+        anchors: BTreeMap::new(),
+        documents: vec![],
+        error: None,
+    };
+    Parser::new(source.chars()).load(&mut builder, false)?;
+    if let Some(error) = builder.error {
+        return Err(error);
+    }
+    let file = builder.file;
+    Ok(builder.documents.into_iter().next().unwrap_or(Node {
+        value: Value::Null,
+        line: 1,
+        file,
+    }))
+}
+
+/// Open collection being filled, with its anchor.
+struct Open {
+    node: Node,
+    anchor: usize,
+}
+
+struct Builder {
+    file: Option<Rc<PathBuf>>,
+    stack: Vec<Open>,
+    /// Key waiting for its value, for every open mapping.
+    keys: Vec<Option<String>>,
+    anchors: BTreeMap<usize, Node>,
+    documents: Vec<Node>,
+    error: Option<ScanError>,
+}
+
+impl MarkedEventReceiver for Builder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        if self.error.is_some() {
+            return;
+        }
+        let line = mark.line();
+        let file = self.file.clone();
+        match event {
+            Event::SequenceStart(anchor) => self.stack.push(Open {
+                node: Node {
+                    value: Value::List(vec![]),
+                    line,
+                    file,
+                },
+                anchor,
+            }),
+            Event::MappingStart(anchor) => {
+                self.stack.push(Open {
+                    node: Node {
+                        value: Value::Map(vec![]),
+                        line,
+                        file,
+                    },
+                    anchor,
+                });
+                self.keys.push(None);
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Event::MappingEnd = event {
+                    self.keys.pop();
+                }
+                if let Some(open) = self.stack.pop() {
+                    self.insert(open.node, open.anchor, mark);
+                }
+            }
+            Event::Scalar(text, style, anchor, _) => {
+                let value = if style != TScalarStyle::Plain {
+                    Value::String(text)
+                } else {
+                    match Yaml::from_str(&text) {
+                        Yaml::Null => Value::Null,
+                        Yaml::Boolean(value) => Value::Boolean(value),
+                        Yaml::Integer(value) => Value::Number(value as f64),
+                        // Also takes `.nan` and `.inf`.
+                        real @ Yaml::Real(_) => match real.as_f64() {
+                            Some(value) => Value::Number(value),
+                            None => Value::String(text),
+                        },
+                        _ => Value::String(text),
+                    }
+                };
+                self.insert(Node { value, line, file }, anchor, mark);
+            }
+            Event::Alias(anchor) => match self.anchors.get(&anchor) {
+                Some(node) => {
+                    let node = node.clone();
+                    self.insert(node, 0, mark);
+                }
+                None => self.error = Some(ScanError::new(mark, "unknown alias")),
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Builder {
+    fn insert(self: &mut Self, node: Node, anchor: usize, mark: Marker) {
+        // Anchor ids start at 1.
+        if anchor > 0 {
+            self.anchors.insert(anchor, node.clone());
+        }
+        let parent = match self.stack.last_mut() {
+            Some(parent) => parent,
+            None => {
+                self.documents.push(node);
+                return;
+            }
+        };
+        match &mut parent.node.value {
+            Value::List(items) => items.push(node),
+            Value::Map(entries) => {
+                let key = self
+                    .keys
+                    .last_mut()
+                    .expect("Mappings always have a key slot");
+                match key.take() {
+                    Some(name) => entries.push((name, node)),
+                    None => match node.value {
+                        Value::String(name) => *key = Some(name),
+                        Value::Number(number) => *key = Some(number.to_string()),
+                        Value::Boolean(value) => *key = Some(value.to_string()),
+                        _ => self.error = Some(ScanError::new(mark, "keys must be plain values")),
+                    },
+                }
+            }
+            _ => unreachable!("Only collections are kept open"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yaml::*;
+
+    #[test]
+    fn scalars_get_their_types() {
+        let node = parse("a: 1\nb: 2.5\nc: true\nd: '3'\ne: ~\nf: text", None).unwrap();
+        assert_eq!(node.get("a").unwrap().value, Value::Number(1.0));
+        assert_eq!(node.get("b").unwrap().value, Value::Number(2.5));
+        assert_eq!(node.get("c").unwrap().value, Value::Boolean(true));
+        assert_eq!(node.get("d").unwrap().value, Value::String("3".to_string()));
+        assert_eq!(node.get("e").unwrap().value, Value::Null);
+        assert_eq!(
+            node.get("f").unwrap().value,
+            Value::String("text".to_string())
+        );
+        assert_eq!(node.get("g"), None);
+    }
+
+    #[test]
+    fn nodes_remember_their_line() {
+        let node = parse("first: 1\nlist:\n  - 2\n  - [3, 4]\n", None).unwrap();
+        assert_eq!(node.get("first").unwrap().line, 1);
+        match &node.get("list").unwrap().value {
+            Value::List(items) => {
+                assert_eq!(items[0].line, 3);
+                assert_eq!(items[1].line, 4);
+            }
+            other => panic!("Expected a list, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nodes_remember_their_file() {
+        let node = parse("a: [1]", Some(Path::new("scene.yaml"))).unwrap();
+        let file = node.get("a").unwrap().file.clone();
+        assert_eq!(file, Some(Rc::new(PathBuf::from("scene.yaml"))));
+    }
+
+    #[test]
+    fn aliases_copy_the_anchored_node() {
+        let node = parse("a: &red [1, 0, 0]\nb: *red", None).unwrap();
+        assert_eq!(node.get("a").unwrap().value, node.get("b").unwrap().value);
+        assert!(parse("a: *missing", None).is_err());
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        let error = parse("a: [1, 2\nb: 3", None).unwrap_err();
+        assert!(error.marker().line() >= 1);
+    }
+}
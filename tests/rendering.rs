@@ -0,0 +1,97 @@
+extern crate alart;
+
+use std::env;
+use std::f32::consts::PI;
+use std::fs;
+
+use alart::canvas::pixel_at;
+use alart::transformations::{scale, translation};
+use alart::{
+    camera, export_exr_layers, export_png, parse_scene, point, Camera, Color, ColorSpace, Error,
+    ExrPrecision, Light, PathTracer, Sphere, World,
+};
+
+fn lit_sphere() -> (Camera, World) {
+    let mut world = World::new();
+    world.objects.push(Sphere::new());
+    world.lights.push(Light::point(
+        point(-10.0, 10.0, -10.0),
+        Color::new(1.0, 1.0, 1.0),
+    ));
+    let mut camera = Camera::new(5, 5, PI / 3.0);
+    camera.transformation = translation(0.0, 0.0, -5.0);
+    (camera, world)
+}
+
+#[test]
+fn a_world_built_in_code_renders() {
+    let (camera, world) = lit_sphere();
+    let canvas = camera::render(&camera, &world).unwrap();
+    assert_ne!(pixel_at(&canvas, 2, 2), Color::black());
+    assert_eq!(pixel_at(&canvas, 0, 0), Color::black());
+
+    let canvas = PathTracer::new(4).render(&camera, &world).unwrap();
+    assert_eq!((canvas.width(), canvas.height()), (5, 5));
+}
+
+#[test]
+fn renders_are_saved_as_png() {
+    let (camera, world) = lit_sphere();
+    let canvas = camera::render(&camera, &world).unwrap();
+    let folder = env::temp_dir().join(format!("alart-rendering-{}", std::process::id()));
+    fs::create_dir_all(&folder).unwrap();
+    let filename = folder.join("sphere.png");
+    export_png(&canvas, filename.to_str().unwrap(), ColorSpace::Srgb).unwrap();
+    assert!(fs::metadata(&filename).unwrap().len() > 0);
+
+    let png = fs::read(&filename).unwrap();
+    assert!(png.windows(4).any(|name| name == b"sRGB"));
+
+    let missing = folder.join("missing").join("sphere.png");
+    let error = export_png(&canvas, missing.to_str().unwrap(), ColorSpace::Srgb).unwrap_err();
+    assert_eq!(error.exit_code(), 74);
+    fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn flattened_objects_are_not_rendered() {
+    let (camera, mut world) = lit_sphere();
+    world.objects[0].transformation = scale(1.0, 0.0, 1.0);
+    match PathTracer::new(1).render(&camera, &world) {
+        Err(Error::SingularTransformation { object }) => assert_eq!(object, Some(0)),
+        other => panic!(
+            "Expected a singular transformation, got {:?}",
+            other.is_ok()
+        ),
+    }
+}
+
+#[test]
+fn passes_are_saved_as_exr_layers() {
+    let (camera, world) = lit_sphere();
+    let passes = PathTracer::new(2).render_passes(&camera, &world).unwrap();
+    assert_eq!(pixel_at(&passes.object_id, 2, 2), Color::new(1.0, 1.0, 1.0));
+    let layers = passes.layers();
+    assert_eq!(layers[0].0, "beauty");
+    let filename = env::temp_dir().join(format!("alart-passes-{}.exr", std::process::id()));
+    export_exr_layers(&layers, filename.to_str().unwrap(), ExrPrecision::Float).unwrap();
+    assert!(fs::metadata(&filename).unwrap().len() > 0);
+    fs::remove_file(&filename).unwrap();
+}
+
+#[test]
+fn scenes_are_read_from_yaml() {
+    let scene = parse_scene(
+        "
+camera: { width: 8, height: 4, from: [0, 0, -5], to: [0, 0, 0] }
+lights:
+  - { type: point, position: [-10, 10, -10], intensity: 1 }
+objects:
+  - { type: sphere, material: { color: [1, 0.2, 0.2] } }
+",
+    )
+    .unwrap();
+    assert_eq!(scene.world.objects.len(), 1);
+    let canvas = camera::render(&scene.camera, &scene.world).unwrap();
+    assert_eq!((canvas.width(), canvas.height()), (8, 4));
+}